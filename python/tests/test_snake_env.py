@@ -0,0 +1,57 @@
+import numpy as np
+import pytest
+
+import snake_rust
+
+
+def test_grid_observation_shape():
+    env = snake_rust.SnakeEnv(board_length=20, observation="grid")
+    observation = env.reset(seed=1)
+
+    assert isinstance(observation, np.ndarray)
+    assert observation.dtype == np.float32
+    assert observation.shape == (5, 20, 20)
+    assert observation[1].sum() == 1.0
+
+
+def test_rays_observation_shape():
+    env = snake_rust.SnakeEnv(observation="rays")
+
+    assert env.reset(seed=1).shape == (24,)
+
+
+def test_same_seed_same_episode():
+    def run():
+        env = snake_rust.SnakeEnv(mode="portal", wall="fluid", observation="snapshot")
+        env.reset(seed=7)
+        snapshots = []
+        for index in range(500):
+            observation, _, done, _ = env.step(index % 4 if index % 9 == 0 else None)
+            snapshots.append((observation["food"], observation["gates"]))
+            if done:
+                break
+        return snapshots
+
+    assert run() == run()
+
+
+def test_solid_wall_ends_episode():
+    env = snake_rust.SnakeEnv(wall="solid", observation="snapshot", reward_death=-5.0)
+    env.reset(seed=1)
+
+    for _ in range(100):
+        _, reward, done, info = env.step(0)
+        if done:
+            break
+
+    assert done
+    assert reward == -5.0
+    assert info["score"] == 0
+
+
+def test_invalid_action():
+    env = snake_rust.SnakeEnv()
+    env.reset()
+
+    with pytest.raises(ValueError):
+        env.step(4)
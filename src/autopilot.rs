@@ -0,0 +1,409 @@
+use crate::board::Board;
+use crate::game_mode::{Mode, Wall};
+use crate::hamiltonian::HamiltonianCycle;
+use crate::portal::{PortalBehavior, PortalExit};
+use crate::snake::Direction;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+
+const DIRECTIONS: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+const SHORTCUT_MARGIN: usize = 4;
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum Strategy {
+    Pathfinding,
+    Hamiltonian,
+}
+
+pub struct Autopilot {
+    pub strategy: Strategy,
+    pub path: Vec<(i32, i32)>,
+    cycle: Option<HamiltonianCycle>,
+}
+
+impl Autopilot {
+    pub fn new(strategy: Strategy) -> Autopilot {
+        Autopilot {
+            strategy,
+            path: Vec::new(),
+            cycle: None,
+        }
+    }
+
+    pub fn next_direction(&mut self, board: &Board) -> Option<Direction> {
+        // A turn already waits for the next cell, the plan is made again from there
+        if board.snakes[0].pending_turns() > 0 {
+            return None;
+        }
+
+        let map = PathMap::new(board);
+
+        self.path.clear();
+
+        // Gates, walls, enemies and a second snake break the cycle order, those boards are left to the pathfinding
+        if self.strategy == Strategy::Hamiltonian
+            && board.game_mode.mode == Mode::Default
+            && board.snakes.len() == 1
+            && board.enemies.is_empty()
+            && board.config.computed_config.level.walls.is_empty() {
+            if self.cycle.is_none() {
+                self.cycle = HamiltonianCycle::new(map.size, map.wall_fluid);
+            }
+
+            if self.cycle.is_some() {
+                return self.follow_cycle(board, &map);
+            }
+        }
+
+        self.find_path(board, &map)
+    }
+
+    // The food is only chased along a path that still leaves the tail in reach once the snake grew,
+    // otherwise the snake takes the longest way round to its tail until the board opens up
+    fn find_path(&mut self, board: &Board, map: &PathMap) -> Option<Direction> {
+        let direction = board.snakes[0].direction();
+        let start = map.head_cell(board);
+
+        let food = (board.food.cell.x, board.food.cell.y);
+        let tail = map.tail_cell(board);
+
+        // The tail leaves its cell on the next move
+        let mut blocked = map.blocked.clone();
+        blocked.remove(&tail);
+
+        if let Some(path) = map.search(start, direction, food, &blocked) {
+            if map.tail_distance(board, &path, true).is_some() {
+                self.path = path.iter().map(|(cell, _)| *cell).collect();
+
+                return path.get(1).map(|(_, direction)| *direction);
+            }
+        }
+
+        let moves: Vec<(Direction, (i32, i32))> = DIRECTIONS
+            .iter()
+            .filter(|next| next.opposite() != direction)
+            .filter_map(|next| map.neighbour(start, *next).map(|cell| (*next, cell)))
+            .filter(|(_, cell)| !blocked.contains(cell))
+            .collect();
+
+        moves
+            .iter()
+            .filter_map(|(next, cell)| {
+                let step = [(start, direction), (*cell, *next)];
+
+                map.tail_distance(board, &step, *cell == food).map(|distance| (*next, distance))
+            })
+            .max_by_key(|(_, distance)| *distance)
+            .map(|(next, _)| next)
+            .or_else(|| moves.first().map(|(next, _)| *next))
+    }
+
+    fn follow_cycle(&mut self, board: &Board, map: &PathMap) -> Option<Direction> {
+        let cycle = self.cycle.as_ref().unwrap();
+        let direction = board.snakes[0].direction();
+        let start = map.head_cell(board);
+
+        let food = (board.food.cell.x, board.food.cell.y);
+        let tail = map.tail_cell(board);
+        if !cycle.contains(start) || !cycle.contains(tail) {
+            return self.find_path(board, map);
+        }
+
+        let food_distance = if cycle.contains(food) { cycle.distance(start, food) } else { cycle.len() };
+
+        let candidates: Vec<(Direction, (i32, i32))> = DIRECTIONS
+            .iter()
+            .filter(|next| next.opposite() != direction)
+            .filter_map(|next| map.neighbour(start, *next).map(|cell| (*next, cell)))
+            .collect();
+
+        // The body always lies on the cycle between the tail and the head,
+        // a shortcut is safe while it lands ahead of the head and behind the tail
+        let mut choice = candidates.iter().find(|(_, cell)| *cell == cycle.next(start)).copied();
+
+        if map.blocked.len() < cycle.len() / 2 {
+            let shortcut = candidates
+                .iter()
+                .filter(|(_, cell)| !map.blocked.contains(cell))
+                .filter(|(_, cell)| cycle.distance(start, *cell) <= food_distance)
+                .filter(|(_, cell)| cycle.distance(start, *cell) < cycle.distance(start, tail))
+                .filter(|(_, cell)| cycle.distance(*cell, tail) > SHORTCUT_MARGIN)
+                .max_by_key(|(_, cell)| cycle.distance(start, *cell));
+
+            if let Some(shortcut) = shortcut {
+                choice = Some(*shortcut);
+            }
+        }
+
+        let choice = choice.or_else(|| candidates
+            .iter()
+            .filter(|(_, cell)| !map.blocked.contains(cell))
+            .max_by_key(|(_, cell)| cycle.distance(*cell, tail))
+            .copied()
+        );
+
+        let mut cell = start;
+        for _ in 0..food_distance {
+            self.path.push(cell);
+            cell = cycle.next(cell);
+        }
+
+        choice.map(|(direction, _)| direction)
+    }
+}
+
+struct PathMap {
+    size: i32,
+    wall_fluid: bool,
+    blocked: HashSet<(i32, i32)>,
+    gates: HashMap<(i32, i32), (i32, i32)>,
+    rotate: bool,
+}
+
+impl PathMap {
+    fn new(board: &Board) -> PathMap {
+        let size = (board.config.computed_config.board_size / board.config.computed_config.block_size) as i32;
+
+        let walls = &board.config.computed_config.level.walls;
+
+        let mut blocked: HashSet<(i32, i32)> = board.bodies()
+            .iter()
+            .chain(walls.iter())
+            .copied()
+            .chain(board.enemies.iter().map(|enemy| enemy.cell))
+            .map(|point| (point.x, point.y))
+            .collect();
+
+        // A jump leaves the body split between both gates until the tail is through,
+        // past an eighth of the board the snake keeps off the gates instead
+        let short = board.snakes[0].body.len() * 8 <= (size * size) as usize - walls.len();
+
+        // Closed pairs are plain cells until the snake is through, one-way pairs only lead from their first gate
+        let mut gates = HashMap::new();
+        if board.game_mode.mode == Mode::Portal && board.snakes[0].jump.is_none() {
+            if let Some(portal) = &board.portal {
+                let portals = &board.config.config.portals;
+                let cells: Vec<(i32, i32)> = portal.gates
+                    .iter()
+                    .map(|gate| (gate.cell.x, gate.cell.y))
+                    .collect();
+
+                for (pair, ends) in cells.chunks(2).enumerate() {
+                    if portal.is_used(pair) {
+                        continue;
+                    }
+
+                    gates.insert(ends[0], ends[1]);
+
+                    if portals.behavior != PortalBehavior::OneWay {
+                        gates.insert(ends[1], ends[0]);
+                    }
+                }
+
+                // Food lying on a gate is eaten before the jump, the path ends on the gate itself
+                gates.remove(&(board.food.cell.x, board.food.cell.y));
+            }
+        }
+
+        if !short {
+            blocked.extend(gates.drain().map(|(entry, _)| entry));
+        }
+
+        PathMap {
+            size,
+            wall_fluid: board.game_mode.wall == Wall::Fluid,
+            blocked,
+            gates,
+            rotate: board.config.config.portals.exit == PortalExit::Rotate,
+        }
+    }
+
+    // The head already took its cell, the next request turns it on the way to the following one
+    fn head_cell(&self, board: &Board) -> (i32, i32) {
+        let head = board.snakes[0].head();
+
+        (head.x, head.y)
+    }
+
+    fn tail_cell(&self, board: &Board) -> (i32, i32) {
+        let tail = board.snakes[0].body.back().unwrap();
+
+        (tail.x, tail.y)
+    }
+
+    fn neighbour(&self, cell: (i32, i32), direction: Direction) -> Option<(i32, i32)> {
+        self.land(offset(cell, direction))
+            .map(|cell| *self.gates.get(&cell).unwrap_or(&cell))
+    }
+
+    // A rotating gate turns the snake on its way out
+    fn heading(&self, cell: (i32, i32), direction: Direction) -> Direction {
+        let through_gate = self.land(offset(cell, direction))
+            .is_some_and(|cell| self.gates.contains_key(&cell));
+
+        if self.rotate && through_gate {
+            direction.clockwise()
+        } else {
+            direction
+        }
+    }
+
+    fn land(&self, (x, y): (i32, i32)) -> Option<(i32, i32)> {
+        if self.wall_fluid {
+            return Some((x.rem_euclid(self.size), y.rem_euclid(self.size)));
+        }
+
+        if x < 0 || y < 0 || x >= self.size || y >= self.size {
+            return None;
+        }
+
+        Some((x, y))
+    }
+
+    // Moves the snake along the path and counts the cells from the new head to the tail,
+    // a snake reaching the food grows by one.
+    // After a jump every gate is a plain cell until the tail is through, the way back is searched without them
+    fn tail_distance(&self, board: &Board, path: &[((i32, i32), Direction)], grows: bool) -> Option<usize> {
+        let snake = &board.snakes[0];
+
+        let mut body: VecDeque<(i32, i32)> = snake.body.iter().map(|point| (point.x, point.y)).collect();
+        let length = body.len() + grows as usize;
+
+        let mut blocked = self.blocked.clone();
+        for cell in &body {
+            blocked.remove(cell);
+        }
+
+        for (cell, _) in path.iter().skip(1) {
+            body.push_front(*cell);
+        }
+        body.truncate(length);
+
+        let head = body[0];
+        let tail = body[body.len() - 1];
+        if head == tail {
+            return Some(0);
+        }
+
+        blocked.extend(body.iter().take(body.len() - 1));
+
+        let heading = match path.len() {
+            1 => snake.direction(),
+            n => self.heading(path[n - 2].0, path[n - 1].1),
+        };
+
+        let jumped = path.windows(2).any(|step| {
+            self.land(offset(step[0].0, step[1].1)).is_some_and(|entry| self.gates.contains_key(&entry))
+        });
+
+        if !jumped {
+            return self.search(head, heading, tail, &blocked).map(|path| path.len() - 1);
+        }
+
+        let map = PathMap {
+            size: self.size,
+            wall_fluid: self.wall_fluid,
+            blocked: HashSet::new(),
+            gates: HashMap::new(),
+            rotate: self.rotate,
+        };
+
+        map.search(head, heading, tail, &blocked).map(|path| path.len() - 1)
+    }
+
+    fn search(
+        &self,
+        start: (i32, i32),
+        direction: Direction,
+        target: (i32, i32),
+        blocked: &HashSet<(i32, i32)>,
+    ) -> Option<Vec<((i32, i32), Direction)>> {
+        let mut parents: HashMap<(i32, i32), ((i32, i32), Direction)> = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back((start, direction));
+
+        while let Some((cell, heading)) = queue.pop_front() {
+            if cell == target {
+                let mut path = Vec::new();
+                let mut current = cell;
+
+                while let Some((parent, entered)) = parents.get(&current) {
+                    path.push((current, *entered));
+                    current = *parent;
+                }
+
+                path.push((start, direction));
+                path.reverse();
+
+                return Some(path);
+            }
+
+            for next_direction in DIRECTIONS.iter().filter(|next| next.opposite() != heading) {
+                let next = match self.neighbour(cell, *next_direction) {
+                    Some(next) => next,
+                    None => continue,
+                };
+
+                if next == start || parents.contains_key(&next) || blocked.contains(&next) {
+                    continue;
+                }
+
+                parents.insert(next, (cell, *next_direction));
+                queue.push_back((next, self.heading(cell, *next_direction)));
+            }
+        }
+
+        None
+    }
+}
+
+fn offset((x, y): (i32, i32), direction: Direction) -> (i32, i32) {
+    match direction {
+        Direction::Up => (x, y - 1),
+        Direction::Down => (x, y + 1),
+        Direction::Left => (x - 1, y),
+        Direction::Right => (x + 1, y),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AutopilotConfig, Config, GlobalConfig};
+    use crate::simulation::Simulation;
+
+    use std::sync::Arc;
+
+    // Plays the cycle until the board is cleared and returns the final length
+    fn clear_board(wall: Wall) -> usize {
+        let mut config = Config::new();
+        config.board.board_block_length = 10;
+        config.game.wall = wall;
+        config.game.seed = Some(3);
+        config.game.autopilot = AutopilotConfig::Hamiltonian;
+
+        let mut simulation = Simulation::new(Arc::new(GlobalConfig::from_config(config).unwrap())).unwrap();
+        let mut autopilot = Autopilot::new(Strategy::Hamiltonian);
+
+        loop {
+            let direction = autopilot.next_direction(&simulation.board);
+            let outcome = simulation.step(&[direction]);
+            assert!(!outcome.died);
+
+            if outcome.cleared {
+                return simulation.board.snakes[0].body.len();
+            }
+        }
+    }
+
+    #[test]
+    fn hamiltonian_fills_a_solid_walled_board() {
+        assert_eq!(clear_board(Wall::Solid), 10 * 10);
+    }
+
+    #[test]
+    fn hamiltonian_fills_a_fluid_walled_board() {
+        assert_eq!(clear_board(Wall::Fluid), 10 * 10);
+    }
+}
@@ -1,92 +1,266 @@
-use crate::config::GlobalConfig;
-use crate::food::Food;
+use crate::config::{ConfigError, GlobalConfig};
+use crate::enemy::Enemy;
+use crate::food::{Food, FoodKind};
 use crate::game_mode::GameMode;
-use crate::portal::{Gate, Portal};
+use crate::portal::Portal;
+use crate::power_up::PowerUp;
 use crate::snake::{Snake, Point, FrameHandler};
 
 use rand::seq::SliceRandom;
-use std::collections::LinkedList;
-use std::iter::FromIterator;
+use rand_chacha::ChaCha8Rng;
+use std::collections::{HashSet, LinkedList};
 use std::sync::Arc;
 
 
+const SAMPLE_TRIES: usize = 8;
+
+
 pub struct Board {
     pub config: Arc<GlobalConfig>,
-    pub snake: Snake,
+    pub snakes: Vec<Snake>,
     pub food: Food,
-    pub next_food: Option<Food>,
-    pub current_delta: f64,
+    pub power_up: Option<PowerUp>,
+    pub enemies: Vec<Enemy>,
     pub grid: Grid,
     pub game_mode: Arc<GameMode>,
     pub portal: Option<Portal>,
 }
 
 impl Board {
-    pub fn new(config: Arc<GlobalConfig>, game_mode: Arc<GameMode>, portal: Option<Portal>) -> Board {
-        Board {
-            config: config.clone(),
-            snake: Snake::new(
-                4.0,
-                4.0,
+    pub fn new(
+        config: Arc<GlobalConfig>,
+        game_mode: Arc<GameMode>,
+        portal: Option<Portal>,
+        rng: &mut ChaCha8Rng,
+    ) -> Result<Board, ConfigError> {
+        let level = &config.computed_config.level;
+
+        let snakes: Vec<Snake> = level.spawns
+            .iter()
+            .take(config.computed_config.players)
+            .map(|(spawn, direction)| Snake::new(
+                *spawn,
+                *direction,
+                level.length,
                 FrameHandler::new(config.clone()),
                 game_mode.clone(),
-            ),
-            food: Food::new(),
-            next_food: None,
-            current_delta: 0.0,
-            grid: Grid::new(
-                *Arc::new(&config.computed_config.board_size),
-                *Arc::new(&config.computed_config.block_size),
-            ),
+            ))
+            .collect();
+
+        let mut grid = Grid::new(config.config.board.board_block_length as i32, &level.walls);
+
+        for point in snakes.iter().flat_map(|snake| snake.body.iter()) {
+            grid.occupy(*point);
+        }
+
+        let food_cell = grid
+            .get_random_position(rng, |_| true)
+            .ok_or_else(|| ConfigError::invalid("board.level", "leaves no free cell for the food"))?;
+        let food_kind = FoodKind::random(&config.config.food, rng);
+
+        Ok(Board {
+            config: config.clone(),
+            snakes,
+            food: Food::new(food_cell, food_kind),
+            power_up: None,
+            enemies: Vec::new(),
+            grid,
             game_mode: game_mode.clone(),
             portal,
-        }
+        })
+    }
+
+    pub fn bodies(&self) -> LinkedList<Point> {
+        self.snakes
+            .iter()
+            .flat_map(|snake| snake.body.iter().copied())
+            .collect()
+    }
+
+    // Food, the power-up, gates and enemies, nothing else may spawn on them
+    pub fn is_taken(&self, cell: Point) -> bool {
+        let gate = self.portal.as_ref().is_some_and(|portal| portal.gates.iter().any(|gate| gate.cell == cell));
+
+        self.food.cell == cell
+            || self.power_up.as_ref().is_some_and(|power_up| power_up.cell == cell)
+            || self.enemies.iter().any(|enemy| enemy.cell == cell)
+            || gate
     }
 }
 
+// Cells food, power-ups, gates and enemies can land on, the snakes counted out as they move.
+// Free cells live in a list and every cell knows its slot, so taking or freeing one is O(1)
 #[derive(Clone)]
 pub struct Grid {
-    pub list: Vec<(f64, f64)>
+    size: i32,
+    free: Vec<Point>,
+    slots: Vec<Option<usize>>,
+    snakes: Vec<u32>,
+    playable: Vec<bool>,
 }
 
 impl Grid {
-    pub fn new(board_size: &f64, block_size: &f64) -> Grid {
-        let mut list = Vec::new();
+    pub fn new(size: i32, walls: &HashSet<Point>) -> Grid {
+        let mut grid = Grid {
+            size,
+            free: Vec::new(),
+            slots: vec![None; (size * size) as usize],
+            snakes: vec![0; (size * size) as usize],
+            playable: vec![false; (size * size) as usize],
+        };
 
-        for x in 1..=(*board_size as i32 / *block_size as i32) - 1 {
-            for y in 1..=(*board_size as i32 / *block_size as i32) - 1 {
-                list.push((x as f64, y as f64))
+        for x in 0..size {
+            for y in 0..size {
+                let point = Point { x, y };
+
+                if !walls.contains(&point) {
+                    let index = grid.index(x, y).unwrap();
+
+                    grid.playable[index] = true;
+                    grid.slots[index] = Some(grid.free.len());
+                    grid.free.push(point);
+                }
             }
         }
 
-        Grid { list }
+        grid
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x >= 0 && y >= 0 && x < self.size && y < self.size {
+            Some((y * self.size + x) as usize)
+        } else {
+            None
+        }
     }
 
-    pub fn remove_occupied_positions(mut self, body: LinkedList<Point>, food: &Food, gates: Option<Vec<Option<Gate>>>) -> Grid {
-        let mut body = body.into_iter();
+    // Snakes can overlap, a cell is free again once the last one left it
+    pub fn occupy(&mut self, point: Point) {
+        let index = match self.index(point.x, point.y) {
+            Some(index) => index,
+            None => return,
+        };
+
+        self.snakes[index] += 1;
+
+        if let Some(slot) = self.slots[index].take() {
+            self.free.swap_remove(slot);
 
-        while let Some(Point { x: body_x, y: body_y }) = &body.next() {
-            self.list = Vec::from_iter(self.list.into_iter()
-                .filter(|(x, y)| (*x, *y) != (*body_x, *body_y)));
+            if let Some(moved) = self.free.get(slot) {
+                let moved = self.index(moved.x, moved.y).unwrap();
+                self.slots[moved] = Some(slot);
+            }
         }
+    }
 
-        self.list = Vec::from_iter(self.list.into_iter()
-            .filter(|(x, y)| (*x, *y) != (food.x, food.y)));
+    pub fn vacate(&mut self, point: Point) {
+        let index = match self.index(point.x, point.y) {
+            Some(index) => index,
+            None => return,
+        };
 
-        if let Some(gates) = gates {
-            for gate in gates {
-                if let Some(gate) = gate {
-                    self.list = Vec::from_iter(self.list.into_iter()
-                        .filter(|(x, y)| (*x, *y) != (gate.x, gate.y))
-                    );
-                }
+        self.snakes[index] = self.snakes[index].saturating_sub(1);
+
+        if self.snakes[index] == 0 && self.playable[index] && self.slots[index].is_none() {
+            self.slots[index] = Some(self.free.len());
+            self.free.push(point);
+        }
+    }
+
+    pub fn free_cells(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn is_free(&self, point: Point) -> bool {
+        self.index(point.x, point.y).is_some_and(|index| self.slots[index].is_some())
+    }
+
+    // A few draws find a cell the filter accepts, unless it rejects most of the free cells
+    pub fn get_random_position(&self, rng: &mut ChaCha8Rng, accept: impl Fn(&Point) -> bool) -> Option<Point> {
+        for _ in 0..SAMPLE_TRIES {
+            let position = *self.free.choose(rng)?;
+
+            if accept(&position) {
+                return Some(position);
+            }
+        }
+
+        let positions: Vec<Point> = self.free.iter().filter(|position| accept(position)).copied().collect();
+
+        positions.choose(rng).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    // Every free cell knows its slot and every slot holds its cell, a cell is free when playable and empty
+    fn check(grid: &Grid) {
+        for (slot, point) in grid.free.iter().enumerate() {
+            assert_eq!(grid.slots[grid.index(point.x, point.y).unwrap()], Some(slot));
+        }
+
+        for (index, slot) in grid.slots.iter().enumerate() {
+            let free = grid.playable[index] && grid.snakes[index] == 0;
+            assert_eq!(slot.is_some(), free);
+
+            if let Some(slot) = slot {
+                let point = grid.free[*slot];
+                assert_eq!(grid.index(point.x, point.y), Some(index));
+            }
+        }
+    }
+
+    #[test]
+    fn occupy_and_vacate_keep_the_slots() {
+        let walls = HashSet::from([Point { x: 3, y: 3 }, Point { x: 4, y: 3 }]);
+        let mut grid = Grid::new(10, &walls);
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut taken = Vec::new();
+
+        check(&grid);
+
+        // Snakes overlap, cross walls and leave the board on a wrap, all of it counted once per snake
+        for _ in 0..2000 {
+            if taken.is_empty() || rng.gen_bool(0.55) {
+                let point = Point { x: rng.gen_range(-1..11), y: rng.gen_range(-1..11) };
+
+                grid.occupy(point);
+                taken.push(point);
+            } else {
+                let point = taken.swap_remove(rng.gen_range(0..taken.len()));
+
+                grid.vacate(point);
             }
+
+            check(&grid);
         }
 
-        self
+        for point in taken.drain(..) {
+            grid.vacate(point);
+        }
+
+        check(&grid);
+        assert_eq!(grid.free.len(), 10 * 10 - walls.len());
     }
 
-    pub fn get_random_position(&self) -> (f64, f64) {
-        return *self.list.choose(&mut rand::thread_rng()).unwrap();
+    #[test]
+    fn a_full_grid_has_no_position_left() {
+        let mut grid = Grid::new(4, &HashSet::new());
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let cells: Vec<Point> = grid.free.clone();
+
+        for point in cells.iter() {
+            grid.occupy(*point);
+        }
+
+        assert!(grid.get_random_position(&mut rng, |_| true).is_none());
+
+        grid.vacate(cells[4]);
+
+        assert_eq!(grid.get_random_position(&mut rng, |_| true), Some(cells[4]));
+        assert!(grid.get_random_position(&mut rng, |point| *point != cells[4]).is_none());
     }
-}
\ No newline at end of file
+}
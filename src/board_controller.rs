@@ -1,107 +1,625 @@
-use crate::board::Board;
-use crate::food::Food;
-use crate::game_mode::Mode;
-use crate::portal::Portal;
-use crate::score::Score;
+use snake_rust::autopilot::{Autopilot, Strategy};
+use snake_rust::clock::Clock;
+use snake_rust::config::{AutopilotConfig, Config, ConfigError, ControlScheme, ControlsConfig, GlobalConfig, SECOND_PLAYER_KEYS};
+use snake_rust::game_mode::{Mode, Wall};
+use snake_rust::highscore::{today, HighScoreEntry, HighScores};
+use snake_rust::key;
+use snake_rust::replay::{Replay, ReplayPlayer};
+use snake_rust::simulation::Simulation;
+use snake_rust::snake::{DeathCause, Direction, TURN_QUEUE};
 
-use piston_window::{GenericEvent, Button};
-use crossbeam_utils::thread;
-use std::sync::{Arc, Mutex};
+use piston_window::{GenericEvent, Button, Key};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 
+pub enum ReplayMode {
+    Off,
+    Record(Replay, PathBuf),
+    Playback(ReplayPlayer),
+}
+
+pub enum GameState {
+    Title,
+    Playing,
+    Paused,
+    // No cause when the board was cleared
+    GameOver {
+        player: usize,
+        score: u32,
+        length: u32,
+        cause: Option<DeathCause>,
+    },
+    Settings {
+        selected: usize,
+        config: Config,
+    },
+    Leaderboard,
+}
+
+const SETTINGS: [&str; 4] = ["Mode", "Wall", "Level", "Autopilot"];
+
 pub struct BoardController {
-    pub board: Board,
-    pub score: Score,
+    pub simulation: Simulation,
+    pub state: GameState,
+    pub replay_mode: ReplayMode,
+    pub autopilot: Option<Autopilot>,
+    pub high_scores: HighScores,
+    pub high_score_key: String,
+    pub name_entries: VecDeque<NameEntry>,
+    pub clock: Clock,
+    // Set by the screenshot key, the window saves the next frame it draws
+    pub screenshot: bool,
+    inputs: Vec<VecDeque<Direction>>,
+    round_start: u64,
+}
+
+pub struct NameEntry {
+    pub player: usize,
+    pub name: String,
+    entry: HighScoreEntry,
 }
 
 impl BoardController {
-    pub fn new(mut board: Board, score: Score) -> BoardController {
-        if board.game_mode.mode == Mode::Portal {
-            let portal = Portal::new(&board);
-            board.portal = Some(portal);
+    pub fn new(config: Arc<GlobalConfig>, replay_mode: ReplayMode, high_scores: HighScores) -> Result<BoardController, ConfigError> {
+        // A replay starts right away and runs unattended
+        let state = match replay_mode {
+            ReplayMode::Playback(_) => GameState::Playing,
+            _ => GameState::Title,
+        };
+
+        let mut board_controller = BoardController {
+            autopilot: new_autopilot(config.config.game.autopilot),
+            inputs: vec![VecDeque::new(); config.computed_config.players],
+            high_score_key: HighScores::key(&config.config),
+            clock: Clock::new(&config),
+            screenshot: false,
+            simulation: Simulation::new(config)?,
+            state,
+            replay_mode,
+            high_scores,
+            name_entries: VecDeque::new(),
+            round_start: 0,
+        };
+
+        board_controller.load_best();
+
+        Ok(board_controller)
+    }
+
+    pub fn event<E: GenericEvent>(&mut self, e: &E) {
+        if let (Some(false), GameState::Playing) = (e.focus_args(), &self.state) {
+            self.state = GameState::Paused;
+        }
+
+        if e.press_args() == Some(Button::Keyboard(window_key(self.controls().screenshot))) {
+            self.screenshot = true;
         }
 
-        BoardController {
-            board,
-            score,
+        match self.state {
+            GameState::Title => self.title_event(e),
+            GameState::Playing => self.playing_event(e),
+            GameState::Paused => self.paused_event(e),
+            GameState::GameOver { .. } => self.game_over_event(e),
+            GameState::Settings { .. } => self.settings_event(e),
+            GameState::Leaderboard => self.leaderboard_event(e),
         }
     }
 
-    pub fn event<E: GenericEvent>(&mut self, e: &E) {
-        if let Some(Button::Keyboard(key)) = e.press_args() {
-            self.board.snake.request_direction(key)
+    fn title_event<E: GenericEvent>(&mut self, e: &E) {
+        match e.press_args() {
+            Some(Button::Keyboard(Key::Return)) | Some(Button::Keyboard(Key::Space)) => self.start(),
+            // The replay file keeps the config it was started with
+            Some(Button::Keyboard(Key::S)) if matches!(self.replay_mode, ReplayMode::Off) => {
+                self.state = GameState::Settings {
+                    selected: 0,
+                    config: (*self.simulation.board.config.config).clone(),
+                };
+            }
+            Some(Button::Keyboard(Key::L)) => self.state = GameState::Leaderboard,
+            _ => {}
         }
+    }
 
-        if let Some(args) = e.update_args() {
-            if self.board.snake.is_dead(&self.board.config.computed_config.board_size, &self.board.config.computed_config.block_size) {
-                self.score.reset();
+    fn playing_event<E: GenericEvent>(&mut self, e: &E) {
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            let controls = self.controls();
+
+            if key == window_key(controls.pause) {
+                self.state = GameState::Paused;
+                return;
+            }
+
+            if self.is_playback() {
+                return;
+            }
+
+            if key == window_key(controls.restart) {
+                if let ReplayMode::Record(replay, _) = &mut self.replay_mode {
+                    replay.record_restart(self.simulation.steps);
+                }
+
+                self.restart();
+                self.start();
+                return;
+            }
+
+            if key == window_key(controls.autopilot) {
+                self.toggle_autopilot();
+            }
 
-                self.board = Board::new(
-                    self.board.config.clone(),
-                    self.board.game_mode.clone(),
-                    Some(Portal::new(&self.board)),
-                );
+            if let Some((player, direction)) = self.key_direction(key) {
+                if let Some(input) = self.inputs.get_mut(player).filter(|input| input.len() < TURN_QUEUE) {
+                    input.push_back(direction);
+                }
             }
+        }
+
+        if let Some(args) = e.update_args() {
+            self.clock.add(args.dt);
 
-            self.board.current_delta += args.dt;
+            while self.clock.tick() {
+                self.step();
 
-            if self.board.config.computed_config.move_delay <= self.board.current_delta {
-                self.board.current_delta = 0.0;
+                if !matches!(self.state, GameState::Playing) {
+                    break;
+                }
             }
+        }
+    }
 
-            self.board.snake.next_head = Some(
-                self.board.snake.get_next_point(
-                    &self.board.config.computed_config.board_size,
-                    &self.board.config.computed_config.block_size,
-                )
-            );
-            if self.board.snake.next_move_eat(&self.board.food) {
-                self.board.food = self.get_next_food().unwrap();
-                self.board.next_food = None;
-                self.board.snake.just_eat = true;
-                self.score.update_score();
+    // One simulation step with the oldest key pressed by each player, the others wait for the next steps
+    fn step(&mut self) {
+        if let ReplayMode::Playback(player) = &self.replay_mode {
+            for _ in 0..player.restarts_at(self.simulation.steps) {
+                self.restart();
             }
+        }
+
+        let mut inputs: Vec<Option<Direction>> = self.inputs
+            .iter_mut()
+            .map(|input| input.pop_front())
+            .collect();
 
-            if self.board.game_mode.mode == Mode::Portal {
-                if let Some(_jump) = self.board.snake.jump {
-                    if !self.board.snake.in_gate() {
-                        self.board.portal = Some(Portal::new(&self.board));
-                        self.board.snake.jump = None;
+        // The autopilot drives player 1
+        if let Some(autopilot) = self.autopilot.as_mut() {
+            inputs[0] = autopilot.next_direction(&self.simulation.board);
+        }
+
+        let inputs = match &mut self.replay_mode {
+            ReplayMode::Off => inputs,
+            ReplayMode::Record(replay, _) => {
+                for (player, input) in inputs.iter().enumerate() {
+                    if let Some(direction) = input {
+                        replay.record(self.simulation.steps, player, *direction);
                     }
                 }
 
-                if !self.board.portal.as_ref().unwrap().is_used(self.board.portal.clone())
-                    && self.board.snake.next_move_take_gate(self.board.portal.as_mut().unwrap()) {
-                    self.board.snake.teleport(self.board.portal.clone().unwrap());
+                inputs
+            }
+            ReplayMode::Playback(player) => player.inputs(self.simulation.steps, inputs.len()),
+        };
+
+        let outcome = self.simulation.step(&inputs);
+
+        if outcome.cleared || outcome.died {
+            self.save_replay();
+
+            if outcome.died && self.is_playback() {
+                self.restart();
+                return;
+            }
+
+            self.end_round();
+        }
+    }
+
+    fn paused_event<E: GenericEvent>(&mut self, e: &E) {
+        let pause = window_key(self.controls().pause);
+
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            if key == pause || key == Key::Return || key == Key::Space {
+                self.clock.reset();
+                self.state = GameState::Playing;
+            }
+        }
+    }
+
+    // The names are typed first, then the leaderboard shows where they landed
+    fn game_over_event<E: GenericEvent>(&mut self, e: &E) {
+        if let Some(name_entry) = self.name_entries.front_mut() {
+            if let Some(text) = e.text_args() {
+                name_entry.name.extend(text.chars().filter(|c| !c.is_control()));
+                name_entry.name.truncate(12);
+            }
+
+            match e.press_args() {
+                Some(Button::Keyboard(Key::Backspace)) => {
+                    name_entry.name.pop();
                 }
+                Some(Button::Keyboard(Key::Return)) => self.submit_name(),
+                _ => {}
             }
 
-            self.board.snake.update(args.dt);
+            return;
+        }
+
+        let restart = window_key(self.controls().restart);
+
+        match e.press_args() {
+            Some(Button::Keyboard(Key::Return)) | Some(Button::Keyboard(Key::Space)) => self.start(),
+            Some(Button::Keyboard(key)) if key == restart => self.start(),
+            Some(Button::Keyboard(Key::T)) => self.state = GameState::Title,
+            Some(Button::Keyboard(Key::L)) => self.state = GameState::Leaderboard,
+            _ => {}
         }
     }
 
-    pub fn get_next_food(&self) -> Option<Food> {
-        let local_self = Arc::new(self);
+    fn settings_event<E: GenericEvent>(&mut self, e: &E) {
+        let (selected, config) = match &mut self.state {
+            GameState::Settings { selected, config } => (selected, config),
+            _ => return,
+        };
 
-        let new_food = Arc::new(Mutex::new(None));
-        let new_food_clone = Arc::clone(&new_food);
+        match e.press_args() {
+            Some(Button::Keyboard(Key::Up)) => *selected = (*selected + SETTINGS.len() - 1) % SETTINGS.len(),
+            Some(Button::Keyboard(Key::Down)) => *selected = (*selected + 1) % SETTINGS.len(),
+            Some(Button::Keyboard(Key::Left)) => change_setting(config, *selected, false),
+            Some(Button::Keyboard(Key::Right)) => change_setting(config, *selected, true),
+            Some(Button::Keyboard(Key::Return)) => {
+                let config = config.clone();
+                self.apply_settings(config);
+                self.state = GameState::Title;
+            }
+            Some(Button::Keyboard(Key::Backspace)) => self.state = GameState::Title,
+            _ => {}
+        }
+    }
 
-        thread::scope(|s| {
-            s.spawn(move |_| {
-                let grid = local_self
-                    .board.grid
-                    .clone()
-                    .remove_occupied_positions(local_self.board.snake.body.clone(), &local_self.board.food, None);
+    fn leaderboard_event<E: GenericEvent>(&mut self, e: &E) {
+        if let Some(Button::Keyboard(_)) = e.press_args() {
+            self.state = GameState::Title;
+        }
+    }
 
-                let (new_x, new_y) = grid.get_random_position();
+    // A finished round is replaced by a new one, an untouched board is played as is
+    fn start(&mut self) {
+        if !self.simulation.dead.is_empty() || self.simulation.cleared {
+            self.restart();
+        }
 
-                *new_food_clone.lock().unwrap() = Some(Food {
-                    x: new_x,
-                    y: new_y,
-                });
+        self.inputs.iter_mut().for_each(|input| input.clear());
+        self.clock.reset();
+        self.state = GameState::Playing;
+    }
+
+    fn restart(&mut self) {
+        self.simulation.restart();
+        self.round_start = self.simulation.steps;
+    }
+
+    // The settings only change what keeps the window size, a new simulation starts with them on the same level
+    fn apply_settings(&mut self, config: Config) {
+        let level = self.simulation.board.config.computed_config.level.clone();
+        let config = Arc::new(GlobalConfig::with_level(config, level));
+
+        self.autopilot = new_autopilot(config.config.game.autopilot);
+        self.high_score_key = HighScores::key(&config.config);
+        self.clock = Clock::new(&config);
+        // Every spawn of the level was counted out when it was loaded, any player count finds a cell for the food
+        self.simulation = Simulation::new(config).expect("the level already built a board");
+        self.round_start = 0;
+
+        self.load_best();
+    }
+
+    fn load_best(&mut self) {
+        let best = self.high_scores.best(&self.high_score_key);
+
+        for score in self.simulation.scores.iter_mut() {
+            score.scores.get_mut("best").unwrap().count = best;
+        }
+    }
+
+    fn snake_length(&self, player: usize) -> u32 {
+        self.simulation.board.snakes[player].body.len() as u32
+    }
+
+    // The first dead player is reported, or the best one when the board was cleared.
+    // Human players whose score makes the table are asked for their name
+    fn end_round(&mut self) {
+        let scores: Vec<u32> = self.simulation.scores.iter().map(|score| score.scores["current"].count).collect();
+
+        let (player, cause) = match self.simulation.dead.first() {
+            Some((player, cause)) => (*player, Some(*cause)),
+            None => ((0..scores.len()).max_by_key(|player| scores[*player]).unwrap(), None),
+        };
+
+        self.state = GameState::GameOver {
+            player,
+            score: scores[player],
+            length: self.snake_length(player),
+            cause,
+        };
+
+        if self.autopilot.is_some() || self.is_playback() {
+            return;
+        }
+
+        let config = &self.simulation.board.config.computed_config;
+        let duration = (self.simulation.steps - self.round_start) as f64 / config.fps;
+
+        for (player, count) in scores.into_iter().enumerate() {
+            if !self.high_scores.qualifies(&self.high_score_key, count, duration) {
+                continue;
+            }
+
+            self.name_entries.push_back(NameEntry {
+                player,
+                name: String::new(),
+                entry: HighScoreEntry {
+                    name: String::new(),
+                    score: count,
+                    length: self.snake_length(player),
+                    duration,
+                    date: today(),
+                },
             });
-        }).unwrap();
+        }
+    }
+
+    fn submit_name(&mut self) {
+        let NameEntry { name, mut entry, .. } = self.name_entries.pop_front().unwrap();
+
+        entry.name = match name.trim() {
+            "" => String::from("Anonymous"),
+            name => String::from(name),
+        };
+
+        self.high_scores.insert(&self.high_score_key, entry);
+        if let Err(e) = self.high_scores.save() {
+            eprintln!("Could not save the high scores: {}", e);
+        }
+
+        if self.name_entries.is_empty() {
+            self.state = GameState::Leaderboard;
+        }
+    }
 
-        return *new_food.lock().unwrap();
+    pub fn banner(&self) -> Option<&str> {
+        match &self.replay_mode {
+            ReplayMode::Playback(player) if player.is_finished() => Some("Replay end"),
+            ReplayMode::Playback(_) => Some("Replay"),
+            _ if self.simulation.boost_time > 0.0 => Some("Speed!"),
+            _ => None
+        }
     }
-}
\ No newline at end of file
+
+    // The title and lines drawn over the board in the current state, shared by both frontends
+    pub fn overlay(&self) -> Option<(String, Vec<String>)> {
+        match &self.state {
+            GameState::Title => {
+                let mut lines = vec![String::from("Return to play")];
+                if let ReplayMode::Off = self.replay_mode {
+                    lines.push(String::from("S for settings"));
+                }
+                lines.push(String::from("L for high scores"));
+                lines.push(String::from("Esc to quit"));
+
+                Some((String::from("Snake"), lines))
+            }
+            GameState::Playing => None,
+            GameState::Paused => Some((String::from("Paused"), vec![format!("{} to resume", self.controls().pause)])),
+            GameState::GameOver { player, score, length, cause } => {
+                if let Some(name_entry) = self.name_entries.front() {
+                    let lines = vec![
+                        format!("Player {}", name_entry.player + 1),
+                        String::from("Enter your name"),
+                        format!("{}_", name_entry.name),
+                    ];
+
+                    return Some((String::from("New high score!"), lines));
+                }
+
+                let mut lines = Vec::new();
+                if self.simulation.scores.len() > 1 {
+                    lines.push(format!("Player {}", player + 1));
+                }
+                lines.push(format!("Score : {}", score));
+                lines.push(format!("Length : {}", length));
+                lines.push(match cause {
+                    Some(DeathCause::Wall) => String::from("Hit a wall"),
+                    Some(DeathCause::Itself) => String::from("Bit its own tail"),
+                    Some(DeathCause::Snake(other)) => format!("Ran into player {}", other + 1),
+                    Some(DeathCause::Enemy(kind)) => format!("Caught by a {}", kind),
+                    Some(DeathCause::Poison) => String::from("Poisoned down to nothing"),
+                    None => String::from("No free cell left"),
+                });
+                lines.push(String::new());
+                lines.push(String::from("Return to play again"));
+                lines.push(String::from("T for the title screen"));
+                lines.push(String::from("L for high scores"));
+
+                let title = match cause {
+                    Some(_) => "Game over",
+                    None => "Cleared!",
+                };
+
+                Some((String::from(title), lines))
+            }
+            GameState::Settings { selected, config } => {
+                let values = [
+                    config.game.mode.to_string(),
+                    config.game.wall.to_string(),
+                    config.game.level.to_string(),
+                    config.game.autopilot.to_string(),
+                ];
+
+                let mut lines: Vec<String> = SETTINGS
+                    .iter()
+                    .zip(values.iter())
+                    .enumerate()
+                    .map(|(i, (name, value))| format!(
+                        "{} {:<10} < {} >",
+                        if i == *selected { ">" } else { " " },
+                        name,
+                        value,
+                    ))
+                    .collect();
+
+                lines.push(String::new());
+                lines.push(String::from("Up and Down select, Left and Right change"));
+                lines.push(String::from("Return saves, Backspace cancels"));
+
+                Some((String::from("Settings"), lines))
+            }
+            GameState::Leaderboard => {
+                let mut lines: Vec<String> = self.high_scores
+                    .table(&self.high_score_key)
+                    .iter()
+                    .enumerate()
+                    .map(|(rank, entry)| format!(
+                        "{:>2}. {:<12} {:>4} {:>4} {:>3}:{:02} {}",
+                        rank + 1,
+                        entry.name,
+                        entry.score,
+                        entry.length,
+                        entry.duration as u64 / 60,
+                        entry.duration as u64 % 60,
+                        entry.date,
+                    ))
+                    .collect();
+
+                if lines.is_empty() {
+                    lines.push(String::from("No scores yet"));
+                }
+
+                Some((String::from("High scores"), lines))
+            }
+        }
+    }
+
+    pub fn toggle_autopilot(&mut self) {
+        self.autopilot = match self.autopilot.as_ref().map(|autopilot| autopilot.strategy) {
+            None => Some(Autopilot::new(Strategy::Pathfinding)),
+            Some(Strategy::Pathfinding) => Some(Autopilot::new(Strategy::Hamiltonian)),
+            Some(Strategy::Hamiltonian) => None,
+        };
+    }
+
+    pub fn controls(&self) -> &ControlsConfig {
+        &self.simulation.board.config.config.controls
+    }
+
+    // Player 1 follows the controls, a relative turn starts from the last turn not played yet.
+    // Player 2 keeps W, A, S and D
+    fn key_direction(&self, key: Key) -> Option<(usize, Direction)> {
+        let heading = self.inputs[0]
+            .back()
+            .copied()
+            .unwrap_or_else(|| self.simulation.board.snakes[0].last_heading());
+
+        key_direction(self.controls(), key, heading)
+            .map(|direction| (0, direction))
+            .or_else(|| second_player_direction(key).map(|direction| (1, direction)))
+    }
+
+    pub fn is_playback(&self) -> bool {
+        matches!(self.replay_mode, ReplayMode::Playback(_))
+    }
+
+    pub fn save_replay(&self) {
+        if let ReplayMode::Record(replay, path) = &self.replay_mode {
+            if let Err(e) = replay.save(path) {
+                eprintln!("Could not save the replay to {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+fn new_autopilot(autopilot: AutopilotConfig) -> Option<Autopilot> {
+    match autopilot {
+        AutopilotConfig::Pathfinding => Some(Autopilot::new(Strategy::Pathfinding)),
+        AutopilotConfig::Hamiltonian => Some(Autopilot::new(Strategy::Hamiltonian)),
+        AutopilotConfig::Off => None
+    }
+}
+
+// Left and Right step through the values of the selected setting
+fn change_setting(config: &mut Config, selected: usize, forward: bool) {
+    match SETTINGS[selected] {
+        "Mode" => {
+            config.game.mode = match config.game.mode {
+                Mode::Default => Mode::Portal,
+                Mode::Portal => Mode::Default,
+            };
+        }
+        "Wall" => {
+            config.game.wall = match config.game.wall {
+                Wall::Fluid => Wall::Solid,
+                Wall::Solid => Wall::Fluid,
+            };
+        }
+        "Level" => {
+            let level = if forward { config.game.level + 1 } else { config.game.level.saturating_sub(1) };
+            config.game.level = level.clamp(1, config.max_level());
+        }
+        "Autopilot" => {
+            let values = [AutopilotConfig::Off, AutopilotConfig::Pathfinding, AutopilotConfig::Hamiltonian];
+            let index = values.iter().position(|value| *value == config.game.autopilot).unwrap();
+            let step = if forward { 1 } else { values.len() - 1 };
+
+            config.game.autopilot = values[(index + step) % values.len()];
+        }
+        _ => {}
+    }
+}
+
+// The direction bound to that key, a relative turn starts from the heading
+pub fn key_direction(controls: &ControlsConfig, key: Key, heading: Direction) -> Option<Direction> {
+    match controls.scheme {
+        ControlScheme::Absolute => [
+            (controls.up, Direction::Up),
+            (controls.down, Direction::Down),
+            (controls.left, Direction::Left),
+            (controls.right, Direction::Right),
+        ]
+            .iter()
+            .find(|(bound, _)| window_key(*bound) == key)
+            .map(|(_, direction)| *direction),
+        ControlScheme::Relative if key == window_key(controls.turn_left) => Some(heading.counter_clockwise()),
+        ControlScheme::Relative if key == window_key(controls.turn_right) => Some(heading.clockwise()),
+        ControlScheme::Relative => None,
+    }
+}
+
+pub fn second_player_direction(key: Key) -> Option<Direction> {
+    SECOND_PLAYER_KEYS
+        .iter()
+        .zip([Direction::Up, Direction::Left, Direction::Down, Direction::Right])
+        .find(|(bound, _)| window_key(**bound) == key)
+        .map(|(_, direction)| direction)
+}
+
+// The config names its keys like the window does
+pub fn window_key(key: key::Key) -> Key {
+    macro_rules! keys {
+        ($($name:ident),*) => {
+            match key {
+                $(key::Key::$name => Key::$name,)*
+            }
+        };
+    }
+
+    keys!(
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        D0, D1, D2, D3, D4, D5, D6, D7, D8, D9,
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+        Up, Down, Left, Right,
+        Space, Return, Tab, Backspace,
+        Insert, Delete, Home, End, PageUp, PageDown,
+        LShift, RShift, LCtrl, RCtrl, LAlt, RAlt,
+        Minus, Equals, Comma, Period, Slash, Backslash, Semicolon, Quote, Backquote, LeftBracket, RightBracket
+    )
+}
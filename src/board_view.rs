@@ -1,23 +1,28 @@
-use crate::board_controller::BoardController;
-use crate::config::GlobalConfig;
-use crate::game_mode::Mode;
-use crate::portal::Portal;
-use crate::score::Score;
-use crate::snake::Snake;
+use crate::board_controller::{BoardController, GameState};
+use snake_rust::config::GlobalConfig;
+use snake_rust::enemy::EnemyKind;
+use snake_rust::score::Score;
+use snake_rust::snapshot::BoardSnapshot;
 
 use piston_window::types::Color;
 use piston_window::*;
+use std::io;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use gfx_device_gl::Device;
 
 
 pub struct BoardViewSettings {
     block_size: Arc<f64>,
-    snake_color: Color,
+    snake_colors: [Color; 4],
     board_background_color: Color,
-    food_color: Color,
-    gate_a_color: Color,
-    gate_b_color: Color,
+    food_colors: [Color; 4],
+    wall_color: Color,
+    autopilot_path_color: Color,
+    power_up_colors: [Color; 5],
+    enemy_colors: [Color; 3],
+    gate_colors: [[Color; 2]; 4],
     gate_ellipse_x_ratio: f64,
 }
 
@@ -25,11 +30,43 @@ impl BoardViewSettings {
     pub fn new(block_size: Arc<f64>) -> BoardViewSettings {
         BoardViewSettings {
             block_size,
-            snake_color: [0.18, 0.80, 0.44, 1.0],
+            snake_colors: [
+                [0.18, 0.80, 0.44, 1.0],
+                [0.90, 0.49, 0.13, 1.0],
+                [0.61, 0.35, 0.71, 1.0],
+                [0.95, 0.77, 0.06, 1.0],
+            ],
             board_background_color: [0.204, 0.286, 0.369, 1.0],
-            food_color: [1.0, 0.0, 0.0, 1.0],
-            gate_a_color: [0.043, 0.99, 0.97, 1.0],
-            gate_b_color: [0.99, 0.98, 0.549, 1.0],
+            // Normal, golden, poison and speed food
+            food_colors: [
+                [1.0, 0.0, 0.0, 1.0],
+                [1.0, 0.84, 0.0, 1.0],
+                [0.91, 0.12, 0.55, 1.0],
+                [0.4, 0.6, 1.0, 1.0],
+            ],
+            wall_color: [0.58, 0.65, 0.65, 1.0],
+            autopilot_path_color: [1.0, 1.0, 1.0, 0.25],
+            // Ghost, slow, magnet, shield and multiplier
+            power_up_colors: [
+                [0.85, 0.85, 0.95, 1.0],
+                [0.2, 0.6, 0.86, 1.0],
+                [0.75, 0.22, 0.17, 1.0],
+                [0.1, 0.74, 0.61, 1.0],
+                [0.95, 0.61, 0.07, 1.0],
+            ],
+            // Patrol, ball and chaser
+            enemy_colors: [
+                [0.55, 0.36, 0.96, 1.0],
+                [0.98, 0.55, 0.24, 1.0],
+                [0.6, 0.0, 0.0, 1.0],
+            ],
+            // The first and second gate of each pair
+            gate_colors: [
+                [[0.043, 0.99, 0.97, 1.0], [0.99, 0.98, 0.549, 1.0]],
+                [[0.91, 0.3, 0.9, 1.0], [0.98, 0.72, 0.97, 1.0]],
+                [[0.95, 0.45, 0.1, 1.0], [1.0, 0.76, 0.55, 1.0]],
+                [[0.2, 0.85, 0.3, 1.0], [0.7, 1.0, 0.72, 1.0]],
+            ],
             gate_ellipse_x_ratio: 1.22,
         }
     }
@@ -59,6 +96,8 @@ struct ScoreViewSettings {
     background_color: Color,
     title_color: Color,
     title_size: u32,
+    banner_color: Color,
+    overlay_color: Color,
 }
 
 impl ScoreViewSettings {
@@ -69,6 +108,8 @@ impl ScoreViewSettings {
             background_color: [0.0, 0.0, 0.0, 1.0],
             title_color: [1.0; 4],
             title_size: 22,
+            banner_color: [1.0, 0.0, 0.0, 1.0],
+            overlay_color: [0.0, 0.0, 0.0, 0.85],
         }
     }
 }
@@ -104,23 +145,109 @@ impl BoardView {
         device: &mut Device,
         _args: &RenderArgs,
     ) {
-        clear(self.board_settings.board_background_color, graphics);
+        let mut snapshot = BoardSnapshot::new(&controller.simulation);
 
-        self.draw_grid(context, graphics);
-        self.draw_snake(&controller.board.snake, context, graphics);
-        self.draw_scores(&controller.score, context, graphics, device);
+        // The snakes carry on between two steps, the window draws more often than the simulation steps
+        if let GameState::Playing = controller.state {
+            snapshot.interpolate(controller.clock.alpha() * controller.simulation.step_progress());
+        }
 
-        //Food
-        self.draw_block(
-            self.board_settings.food_color,
-            *&controller.board.food.x,
-            *&controller.board.food.y,
+        self.draw_snapshot(
+            &snapshot,
+            &controller.simulation.scores,
+            controller.banner(),
             context,
             graphics,
+            device,
+        );
+
+        if let (GameState::Playing, Some(autopilot)) = (&controller.state, &controller.autopilot) {
+            self.draw_path(&autopilot.path, context, graphics);
+        }
+
+        if let Some((title, lines)) = controller.overlay() {
+            self.draw_overlay(&title, &lines, context, graphics, device);
+        }
+    }
+
+    fn draw_overlay(&mut self, title: &str, lines: &[String], context: &Context, graphics: &mut G2d, device: &mut Device) {
+        rectangle(
+            self.score_settings.overlay_color,
+            [0.0, 0.0, *self.score_settings.board_size, *self.score_settings.board_size],
+            context.transform,
+            graphics,
         );
 
-        if controller.board.game_mode.mode == Mode::Portal {
-            self.draw_gates(controller.board.portal.as_mut().unwrap(), context, graphics);
+        text(
+            self.score_settings.title_color,
+            self.score_settings.title_size,
+            title,
+            &mut self.glyphs,
+            context.transform.trans(20.0, 40.0),
+            graphics,
+        ).unwrap();
+
+        for (i, line) in lines.iter().enumerate() {
+            text(
+                self.score_settings.title_color,
+                self.score_settings.title_size - 12,
+                line,
+                &mut self.glyphs,
+                context.transform.trans(20.0, 80.0 + i as f64 * 24.0),
+                graphics,
+            ).unwrap();
+        }
+
+        self.glyphs.factory.encoder.flush(device);
+    }
+
+    // Draws a board state, either from the local simulation or received from a server
+    pub fn draw_snapshot(
+        &mut self,
+        snapshot: &BoardSnapshot,
+        scores: &[Score],
+        banner: Option<&str>,
+        context: &Context,
+        graphics: &mut G2d,
+        device: &mut Device,
+    ) {
+        clear(self.board_settings.board_background_color, graphics);
+
+        self.draw_grid(context, graphics);
+
+        for (x, y) in snapshot.walls.iter() {
+            self.draw_block(self.board_settings.wall_color, *x as f64, *y as f64, context, graphics);
+        }
+
+        for (player, snake) in snapshot.snakes.iter().enumerate() {
+            self.draw_snake(&snake.blocks(), self.board_settings.snake_colors[player], context, graphics);
+        }
+        self.draw_scores(scores, snapshot, banner, context, graphics, device);
+
+        //Food
+        if !snapshot.cleared {
+            self.draw_block(
+                self.board_settings.food_colors[snapshot.food_kind as usize],
+                snapshot.food.0 as f64,
+                snapshot.food.1 as f64,
+                context,
+                graphics,
+            );
+        }
+
+        if let Some((x, y, kind)) = snapshot.power_up {
+            self.draw_power_up(self.board_settings.power_up_colors[kind as usize], x as f64, y as f64, context, graphics);
+        }
+
+        self.draw_gates(&snapshot.gates, context, graphics);
+
+        for (x, y, kind) in snapshot.enemies.iter() {
+            let color = self.board_settings.enemy_colors[*kind as usize];
+
+            match kind {
+                EnemyKind::Ball => self.draw_power_up(color, *x as f64, *y as f64, context, graphics),
+                _ => self.draw_block(color, *x as f64, *y as f64, context, graphics),
+            }
         }
     }
 
@@ -150,15 +277,21 @@ impl BoardView {
         }
     }
 
-    fn draw_snake(&self, snake: &Snake, context: &Context, graphics: &mut G2d) {
-        for point in &snake.body {
-            self.draw_block(self.board_settings.snake_color, point.x, point.y, &context, graphics)
+    fn draw_snake(&self, body: &[(f64, f64)], color: Color, context: &Context, graphics: &mut G2d) {
+        for (x, y) in body {
+            self.draw_block(color, *x, *y, context, graphics)
+        }
+    }
+
+    fn draw_path(&self, path: &[(i32, i32)], context: &Context, graphics: &mut G2d) {
+        for (x, y) in path {
+            self.draw_block(self.board_settings.autopilot_path_color, *x as f64, *y as f64, context, graphics)
         }
     }
 
     pub fn draw_block(&self, color: Color, x: f64, y: f64, context: &Context, graphics: &mut G2d) {
-        let gui_x = x as f64 * *self.board_settings.block_size;
-        let gui_y = y as f64 * *self.board_settings.block_size;
+        let gui_x = x * *self.board_settings.block_size;
+        let gui_y = y * *self.board_settings.block_size;
 
         rectangle(
             color,
@@ -169,8 +302,8 @@ impl BoardView {
     }
 
     pub fn draw_ellipse(&self, color: Color, x: f64, y: f64, context: &Context, graphics: &mut G2d) {
-        let gui_x = x as f64 * *self.board_settings.block_size;
-        let gui_y = y as f64 * *self.board_settings.block_size;
+        let gui_x = x * *self.board_settings.block_size;
+        let gui_y = y * *self.board_settings.block_size;
 
         let ellipse_x_size = *self.board_settings.block_size / self.board_settings.gate_ellipse_x_ratio;
         let fixed_x = gui_x + ((*self.board_settings.block_size - ellipse_x_size) / 2.0);
@@ -183,7 +316,15 @@ impl BoardView {
         );
     }
 
-    pub fn draw_scores(&mut self, score: &Score, context: &Context, graphics: &mut G2d, device: &mut Device) {
+    pub fn draw_scores(
+        &mut self,
+        scores: &[Score],
+        snapshot: &BoardSnapshot,
+        banner: Option<&str>,
+        context: &Context,
+        graphics: &mut G2d,
+        device: &mut Device,
+    ) {
         let end_x = *self.score_settings.board_size + *self.score_settings.score_size;
 
         rectangle(
@@ -197,51 +338,106 @@ impl BoardView {
             graphics,
         );
 
+        // One column per player
+        let column_size = *self.score_settings.score_size / scores.len() as f64;
+
+        for (column, score) in scores.iter().enumerate() {
+            let column_x = *self.score_settings.board_size + column as f64 * column_size;
+
+            text(
+                self.score_settings.title_color,
+                self.score_settings.title_size,
+                &score.title,
+                &mut self.glyphs,
+                context.transform.trans(
+                    column_x + 20.0,
+                    40.0,
+                ),
+                graphics,
+            ).unwrap();
+
+            let mut i = 2.0;
+            for (_k, mut score_element) in score.scores
+                .clone()
+                .into_iter() {
+                text(
+                    self.score_settings.title_color,
+                    self.score_settings.title_size - 10,
+                    &format!("{} : {}", ascii_uc_first(&mut score_element.title), &score_element.count),
+                    &mut self.glyphs,
+                    context.transform.trans(
+                        column_x + 10.0,
+                        i * 60.0,
+                    ),
+                    graphics,
+                ).unwrap();
+
+                i += 1.0;
+            }
+
+            // Remaining seconds of each active power-up
+            let effects = snapshot.snakes.get(column).map(|snake| &snake.effects[..]).unwrap_or(&[]);
+
+            for (j, (kind, remaining)) in effects.iter().enumerate() {
+                text(
+                    self.board_settings.power_up_colors[*kind as usize],
+                    self.score_settings.title_size - 12,
+                    &format!("{} {:.1}s", ascii_uc_first(&mut kind.to_string()), remaining),
+                    &mut self.glyphs,
+                    context.transform.trans(
+                        column_x + 10.0,
+                        i * 60.0 + j as f64 * 20.0,
+                    ),
+                    graphics,
+                ).unwrap();
+            }
+        }
+
         text(
             self.score_settings.title_color,
-            self.score_settings.title_size,
-            &*score.title,
+            self.score_settings.title_size - 10,
+            &format!("Level : {}", snapshot.level),
             &mut self.glyphs,
             context.transform.trans(
-                &*self.score_settings.board_size + 20.0,
-                40.0,
+                *self.score_settings.board_size + 10.0,
+                80.0,
             ),
             graphics,
         ).unwrap();
 
-        let mut i = 2.0;
-        for (_k, mut score_element) in score.scores
-            .clone()
-            .into_iter() {
+        if let Some(banner) = banner {
             text(
-                self.score_settings.title_color,
-                self.score_settings.title_size - 10,
-                &*format!("{} : {}", ascii_uc_first(&mut score_element.title), &score_element.count),
+                self.score_settings.banner_color,
+                self.score_settings.title_size,
+                banner,
                 &mut self.glyphs,
                 context.transform.trans(
-                    &*self.score_settings.board_size + 10.0,
-                    i * 60.0,
+                    *self.score_settings.board_size + 20.0,
+                    *self.score_settings.board_size - 20.0,
                 ),
                 graphics,
             ).unwrap();
-
-            i += 1.0;
         }
 
         self.glyphs.factory.encoder.flush(device);
     }
 
-    fn draw_gates(&self, portal: &mut Portal, context: &Context, graphics: &mut G2d) {
-        for (i, gate) in portal.gates.iter().enumerate() {
-            let color = match i {
-                0 => Some(self.board_settings.gate_a_color),
-                1 => Some(self.board_settings.gate_b_color),
-                _ => None
-            };
+    fn draw_power_up(&self, color: Color, x: f64, y: f64, context: &Context, graphics: &mut G2d) {
+        let block_size = *self.board_settings.block_size;
+
+        ellipse(
+            color,
+            [x * block_size, y * block_size, block_size, block_size],
+            context.transform,
+            graphics,
+        );
+    }
 
-            let gate = gate.lock().unwrap();
+    fn draw_gates(&self, gates: &[(i32, i32)], context: &Context, graphics: &mut G2d) {
+        for (i, (x, y)) in gates.iter().enumerate() {
+            let color = self.board_settings.gate_colors[i / 2][i % 2];
 
-            self.draw_ellipse(color.unwrap(), gate.x, gate.y, context, graphics);
+            self.draw_ellipse(color, *x as f64, *y as f64, context, graphics);
         }
     }
 }
@@ -252,4 +448,38 @@ fn ascii_uc_first(text: &mut str) -> &mut str {
     }
 
     text
-}
\ No newline at end of file
+}
+
+// Reads back the frame just drawn and saves it as a PNG in the working directory
+pub fn screenshot(device: &mut Device, size: [u32; 2]) -> io::Result<PathBuf> {
+    let [width, height] = size;
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+
+    unsafe {
+        device.with_gl(|gl| gl.ReadPixels(
+            0,
+            0,
+            width as i32,
+            height as i32,
+            gfx_gl::RGBA,
+            gfx_gl::UNSIGNED_BYTE,
+            pixels.as_mut_ptr() as *mut _,
+        ));
+    }
+
+    // OpenGL rows start from the bottom
+    let rows: Vec<u8> = pixels
+        .chunks((width * 4) as usize)
+        .rev()
+        .flatten()
+        .copied()
+        .collect();
+
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let path = PathBuf::from(format!("snake-{}.png", seconds));
+
+    ::image::save_buffer(&path, &rows, width, height, ::image::ColorType::Rgba8)
+        .map_err(io::Error::other)?;
+
+    Ok(path)
+}
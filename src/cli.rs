@@ -0,0 +1,117 @@
+use snake_rust::config::{Config, ConfigError};
+use snake_rust::game_mode::{Mode, Wall};
+
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+
+#[derive(Parser)]
+#[command(version, about = "Snake with portals, levels and network multiplayer")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // Config.toml in the working directory, then next to the executable
+    #[arg(long, global = true, value_name = "PATH", help = "Config file to start from")]
+    pub config: Option<PathBuf>,
+
+    #[arg(long, global = true, help = "Game mode: default or portal")]
+    pub mode: Option<Mode>,
+
+    #[arg(long, global = true, help = "Board edges: solid or fluid")]
+    pub wall: Option<Wall>,
+
+    #[arg(long, global = true, help = "Speed level")]
+    pub level: Option<u32>,
+
+    #[arg(long, global = true, value_name = "PATH", help = "Level file with walls, spawns and gates")]
+    pub level_file: Option<PathBuf>,
+
+    #[arg(long, global = true, value_name = "CELLS", help = "Board length in cells, a level file sets its own")]
+    pub board_length: Option<u32>,
+
+    #[arg(long, global = true, value_name = "PIXELS", help = "Cell size in pixels")]
+    pub block_size: Option<u32>,
+
+    // TOML integers are signed, a larger seed could not be written to a replay
+    #[arg(long, global = true, value_parser = clap::value_parser!(u64).range(..=i64::MAX as u64), help = "Seed for the food and portal placement")]
+    pub seed: Option<u64>,
+
+    #[arg(long, global = true, help = "Simulation steps per second, 10 to 240")]
+    pub fps: Option<u32>,
+
+    #[arg(long, global = true, help = "Number of players, 1 or 2 on one keyboard, up to 4 for the server")]
+    pub players: Option<u32>,
+
+    #[arg(long, help = "Play in the terminal instead of a window")]
+    pub tui: bool,
+
+    #[arg(long, value_name = "PATH", conflicts_with = "replay", help = "Record the session to a replay file")]
+    pub record: Option<PathBuf>,
+
+    #[arg(long, value_name = "PATH", help = "Play a replay file back with its recorded config")]
+    pub replay: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Run the authoritative board for the network players")]
+    Server {
+        address: Option<String>,
+    },
+    #[command(about = "Join a server as the next free player")]
+    Connect {
+        address: String,
+    },
+}
+
+impl Cli {
+    // Every flag given on the command line replaces its config value.
+    // A level map sets the board size, a board length given with it would be silently dropped
+    pub fn apply(&self, config: &mut Config) -> Result<(), ConfigError> {
+        if let Some(mode) = self.mode {
+            config.game.mode = mode;
+        }
+
+        if let Some(wall) = self.wall {
+            config.game.wall = wall;
+        }
+
+        if let Some(level) = self.level {
+            config.game.level = level;
+        }
+
+        if let Some(level_file) = &self.level_file {
+            config.board.level = Some(level_file.to_string_lossy().into_owned());
+        }
+
+        if let Some(board_length) = self.board_length {
+            if let Some(level) = &config.board.level {
+                return Err(ConfigError::Invalid {
+                    key: String::from("board.board_block_length"),
+                    message: format!("--board-length cannot resize the level `{}`, its map sets the board size", level),
+                });
+            }
+
+            config.board.board_block_length = board_length;
+        }
+
+        if let Some(block_size) = self.block_size {
+            config.board.block_size = block_size;
+        }
+
+        if let Some(seed) = self.seed {
+            config.game.seed = Some(seed);
+        }
+
+        if let Some(fps) = self.fps {
+            config.game.fps = Some(fps);
+        }
+
+        if let Some(players) = self.players {
+            config.game.players = Some(players);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use crate::config::GlobalConfig;
+
+
+// Longest stretch caught up at once, a longer stall slows the game down instead of running many moves in a row
+const MAX_CATCH_UP: f64 = 0.25;
+
+// Steps the simulation at a fixed rate whatever the frame rate, the time left over carries on to the next frame
+pub struct Clock {
+    tick: f64,
+    accumulator: f64,
+}
+
+impl Clock {
+    pub fn new(config: &GlobalConfig) -> Clock {
+        Clock {
+            tick: 1.0 / config.computed_config.fps,
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, delta_time: f64) {
+        self.accumulator = (self.accumulator + delta_time).min(MAX_CATCH_UP);
+    }
+
+    // True once per tick due, call it until it returns false
+    pub fn tick(&mut self) -> bool {
+        if self.accumulator < self.tick {
+            return false;
+        }
+
+        self.accumulator -= self.tick;
+
+        true
+    }
+
+    // Share of the next tick already elapsed, to draw in between two steps
+    pub fn alpha(&self) -> f64 {
+        self.accumulator / self.tick
+    }
+
+    // Seconds until the next tick is due
+    pub fn remaining(&self) -> f64 {
+        (self.tick - self.accumulator).max(0.0)
+    }
+
+    pub fn reset(&mut self) {
+        self.accumulator = 0.0;
+    }
+}
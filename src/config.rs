@@ -1,9 +1,25 @@
-use serde::Deserialize;
-use std::fs::File;
-use std::io::Read;
+use crate::enemy::Contact;
+use crate::game_mode::{Mode, Wall};
+use crate::key::Key;
+use crate::level::Level;
+use crate::portal::{PortalBehavior, PortalExit};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
 use std::path::Path;
 use std::sync::Arc;
 
+
+const DEFAULT_FPS: u32 = 60;
+// Player 2 steers with these whatever the controls say
+pub const SECOND_PLAYER_KEYS: [Key; 4] = [Key::W, Key::A, Key::S, Key::D];
+// One keyboard steers that many snakes, the others only join a server
+pub const LOCAL_PLAYERS: u32 = 2;
+
 pub struct GlobalConfig {
     pub computed_config: ComputedConfig,
     pub config: Arc<Config>,
@@ -11,61 +27,650 @@ pub struct GlobalConfig {
 
 impl GlobalConfig {
     pub fn new() -> GlobalConfig {
-        let config = Arc::new(Config::new());
+        let config = Config::new();
+        let level = Level::empty(config.board.board_block_length);
+
+        GlobalConfig::with_level(config, level)
+    }
+
+    pub fn from_config(config: Config) -> Result<GlobalConfig, ConfigError> {
+        let level = config.level()?;
+
+        Ok(GlobalConfig::with_level(config, level))
+    }
+
+    // For a level already loaded, a new config on the same board does not read it again
+    pub fn with_level(mut config: Config, level: Level) -> GlobalConfig {
+        // The level map decides the board size
+        config.board.board_block_length = level.size;
+
+        let config = Arc::new(config);
         GlobalConfig {
-            computed_config: ComputedConfig::new(config.clone()),
+            computed_config: ComputedConfig::new(config.clone(), level),
             config: config.clone(),
         }
     }
 }
 
+impl Default for GlobalConfig {
+    fn default() -> GlobalConfig {
+        GlobalConfig::new()
+    }
+}
+
 pub struct ComputedConfig {
     pub board_size: f64,
     pub block_size: f64,
     pub move_delay: f64,
     pub score_size: f64,
     pub fps: f64,
+    pub seed: u64,
+    pub players: usize,
+    pub level: Level,
 }
 
 impl ComputedConfig {
-    pub fn new(config: Arc<Config>) -> ComputedConfig {
+    pub fn new(config: Arc<Config>, level: Level) -> ComputedConfig {
+        let players = (config.game.players.unwrap_or(1).clamp(1, 4) as usize).min(level.spawns.len());
+
         ComputedConfig {
             board_size: (config.board.block_size * config.board.board_block_length) as f64,
             block_size: config.board.block_size as f64,
             move_delay: (config.game.level as f64 / 10.0),
-            score_size: 150.0,
-            fps: 60.0,
+            score_size: 150.0 * players as f64,
+            fps: config.game.fps.unwrap_or(DEFAULT_FPS) as f64,
+            // Kept in the signed range so the seed can be written back to TOML
+            seed: config.game.seed.unwrap_or_else(|| rand::thread_rng().gen_range(0..=i64::MAX as u64)),
+            players,
+            level,
         }
     }
 }
 
 //Toml
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub board: Board,
     pub game: Game,
+    #[serde(default)]
+    pub food: FoodConfig,
+    #[serde(default)]
+    pub power_ups: PowerUpConfig,
+    #[serde(default)]
+    pub portals: PortalConfig,
+    #[serde(default)]
+    pub enemies: EnemyConfig,
+    #[serde(default)]
+    pub controls: ControlsConfig,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct Game {
-    pub mode: String,
-    pub wall: String,
-    level: u32,
+    pub mode: Mode,
+    pub wall: Wall,
+    pub level: u32,
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub autopilot: AutopilotConfig,
+    pub players: Option<u32>,
+    pub fps: Option<u32>,
+    pub speed_up_every: Option<u32>,
+    pub level_cap: Option<u32>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct Board {
     pub board_block_length: u32,
     pub block_size: u32,
+    pub level: Option<String>,
+}
+
+// Spawn weights of each food kind
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct FoodConfig {
+    pub normal: u32,
+    pub golden: u32,
+    pub poison: u32,
+    pub speed: u32,
+}
+
+impl Default for FoodConfig {
+    fn default() -> FoodConfig {
+        FoodConfig {
+            normal: 1,
+            golden: 0,
+            poison: 0,
+            speed: 0,
+        }
+    }
+}
+
+// Seconds between two power-ups and the spawn weight of each kind, all weights at 0 disable them
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct PowerUpConfig {
+    pub interval: f64,
+    pub ghost: u32,
+    pub slow: u32,
+    pub magnet: u32,
+    pub shield: u32,
+    pub multiplier: u32,
+}
+
+impl Default for PowerUpConfig {
+    fn default() -> PowerUpConfig {
+        PowerUpConfig {
+            interval: 10.0,
+            ghost: 0,
+            slow: 0,
+            magnet: 0,
+            shield: 0,
+            multiplier: 0,
+        }
+    }
+}
+
+// Gate pairs placed at random in portal mode, a level with its own gates decides instead
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct PortalConfig {
+    pub pairs: u32,
+    pub behavior: PortalBehavior,
+    pub exit: PortalExit,
+}
+
+impl Default for PortalConfig {
+    fn default() -> PortalConfig {
+        PortalConfig {
+            pairs: 1,
+            behavior: PortalBehavior::Respawn,
+            exit: PortalExit::Keep,
+        }
+    }
+}
+
+// How many enemies of each kind start on the board, none by default
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct EnemyConfig {
+    pub patrol: u32,
+    pub ball: u32,
+    pub chaser: u32,
+    pub contact: Contact,
+}
+
+// Keys by their window name, a second local player keeps W, A, S and D
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct ControlsConfig {
+    pub scheme: ControlScheme,
+    pub up: Key,
+    pub down: Key,
+    pub left: Key,
+    pub right: Key,
+    pub turn_left: Key,
+    pub turn_right: Key,
+    pub pause: Key,
+    pub restart: Key,
+    pub autopilot: Key,
+    pub screenshot: Key,
+}
+
+impl Default for ControlsConfig {
+    fn default() -> ControlsConfig {
+        ControlsConfig {
+            scheme: ControlScheme::Absolute,
+            up: Key::Up,
+            down: Key::Down,
+            left: Key::Left,
+            right: Key::Right,
+            turn_left: Key::Left,
+            turn_right: Key::Right,
+            pause: Key::P,
+            restart: Key::R,
+            autopilot: Key::Tab,
+            screenshot: Key::F12,
+        }
+    }
+}
+
+impl ControlsConfig {
+    // The keys in use with the scheme, by action name
+    pub fn bindings(&self) -> Vec<(&'static str, Key)> {
+        let mut bindings = match self.scheme {
+            ControlScheme::Absolute => vec![("up", self.up), ("down", self.down), ("left", self.left), ("right", self.right)],
+            ControlScheme::Relative => vec![("turn_left", self.turn_left), ("turn_right", self.turn_right)],
+        };
+
+        bindings.extend_from_slice(&[
+            ("pause", self.pause),
+            ("restart", self.restart),
+            ("autopilot", self.autopilot),
+            ("screenshot", self.screenshot),
+        ]);
+
+        bindings
+    }
+}
+
+// Absolute has a key per direction, relative turns left or right from the heading
+#[derive(PartialEq, Copy, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ControlScheme {
+    #[default]
+    Absolute,
+    Relative,
+}
+
+impl fmt::Display for ControlScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ControlScheme::Absolute => write!(f, "absolute"),
+            ControlScheme::Relative => write!(f, "relative"),
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutopilotConfig {
+    #[default]
+    Off,
+    Pathfinding,
+    Hamiltonian,
+}
+
+impl fmt::Display for AutopilotConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AutopilotConfig::Off => write!(f, "off"),
+            AutopilotConfig::Pathfinding => write!(f, "pathfinding"),
+            AutopilotConfig::Hamiltonian => write!(f, "hamiltonian"),
+        }
+    }
 }
 
 impl Config {
+    // Built-in defaults, used when there is no config file
     pub fn new() -> Config {
-        let mut file = File::open(Path::new("./Config.toml")).unwrap();
-        let mut contents = String::new();
+        Config {
+            board: Board {
+                board_block_length: 20,
+                block_size: 20,
+                level: None,
+            },
+            game: Game {
+                mode: Mode::Default,
+                wall: Wall::Solid,
+                level: 4,
+                seed: None,
+                autopilot: AutopilotConfig::Off,
+                players: None,
+                fps: None,
+                speed_up_every: None,
+                level_cap: None,
+            },
+            food: FoodConfig::default(),
+            power_ups: PowerUpConfig::default(),
+            portals: PortalConfig::default(),
+            enemies: EnemyConfig::default(),
+            controls: ControlsConfig::default(),
+        }
+    }
+
+    // A relative level path is read next to the config file, wherever the game runs from.
+    // Validation is left to the caller, once its own overrides are applied
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(ConfigError::Io)?;
+
+        let mut config = Config::read(&contents)?;
+
+        if let (Some(level), Some(directory)) = (&config.board.level, path.parent()) {
+            config.board.level = Some(directory.join(level).to_string_lossy().into_owned());
+        }
+
+        Ok(config)
+    }
+
+    pub fn parse(contents: &str) -> Result<Config, ConfigError> {
+        let config = Config::read(contents)?;
 
-        file.read_to_string(&mut contents).unwrap();
+        config.validate()?;
 
-        toml::from_str(&*contents).unwrap()
+        Ok(config)
     }
-}
\ No newline at end of file
+
+    fn read(contents: &str) -> Result<Config, ConfigError> {
+        toml::from_str(contents).map_err(|e| ConfigError::parse(contents, e))
+    }
+
+    pub fn level(&self) -> Result<Level, ConfigError> {
+        match &self.board.level {
+            Some(path) => Level::load(Path::new(path)).map_err(|error| ConfigError::Level {
+                path: path.clone(),
+                error,
+            }),
+            None => Ok(Level::empty(self.board.board_block_length)),
+        }
+    }
+
+    // Past half a cell per frame the head skips over cells it should collide with
+    pub fn max_level(&self) -> u32 {
+        (5.0 * self.game.fps.unwrap_or(DEFAULT_FPS) as f64 / self.board.block_size as f64) as u32
+    }
+
+    // The level stops rising there, by default at the fastest starting level
+    pub fn level_cap(&self) -> u32 {
+        self.game.level_cap.unwrap_or_else(|| self.max_level())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.validate_with(&self.level()?)
+    }
+
+    // A game on this machine only, without a server for the players past the keyboard
+    pub fn validate_local(&self) -> Result<(), ConfigError> {
+        if self.game.players.unwrap_or(1) > LOCAL_PLAYERS {
+            return Err(ConfigError::invalid(
+                "game.players",
+                &format!("must be at most {} on one keyboard, more players need the server command", LOCAL_PLAYERS),
+            ));
+        }
+
+        Ok(())
+    }
+
+    // The same checks against a level already at hand, such as the map kept in a replay
+    pub fn validate_with(&self, level: &Level) -> Result<(), ConfigError> {
+        if self.board.block_size < 4 {
+            return Err(ConfigError::invalid("board.block_size", "must be at least 4"));
+        }
+
+        if self.board.level.is_none() && self.board.board_block_length < 10 {
+            return Err(ConfigError::invalid("board.board_block_length", "must be at least 10"));
+        }
+
+        if !(10..=240).contains(&self.game.fps.unwrap_or(DEFAULT_FPS)) {
+            return Err(ConfigError::invalid("game.fps", "must be between 10 and 240"));
+        }
+
+        if self.game.seed.is_some_and(|seed| seed > i64::MAX as u64) {
+            return Err(ConfigError::invalid("game.seed", &format!("must be at most {}", i64::MAX)));
+        }
+
+        if self.game.level == 0 {
+            return Err(ConfigError::invalid("game.level", "must be greater than 0"));
+        }
+
+        let max_level = self.max_level();
+        if self.game.level > max_level {
+            return Err(ConfigError::invalid(
+                "game.level",
+                &format!("must be at most {} with a block_size of {}", max_level, self.board.block_size),
+            ));
+        }
+
+        if self.game.speed_up_every == Some(0) {
+            return Err(ConfigError::invalid("game.speed_up_every", "must be greater than 0"));
+        }
+
+        if self.level_cap() < self.game.level {
+            return Err(ConfigError::invalid("game.level_cap", "must be at least the starting level"));
+        }
+
+        let food = &self.food;
+        if food.normal + food.golden + food.poison + food.speed == 0 {
+            return Err(ConfigError::invalid("food", "needs at least one weight above 0"));
+        }
+
+        if self.power_ups.interval <= 0.0 {
+            return Err(ConfigError::invalid("power_ups.interval", "must be greater than 0"));
+        }
+
+        if !(1..=4).contains(&self.portals.pairs) {
+            return Err(ConfigError::invalid("portals.pairs", "must be between 1 and 4"));
+        }
+
+        let enemies = &self.enemies;
+        if enemies.patrol + enemies.ball + enemies.chaser > 10 {
+            return Err(ConfigError::invalid("enemies", "allows at most 10 enemies"));
+        }
+
+        let players = self.game.players.unwrap_or(1);
+
+        // Alone on the keyboard, player 1 may take W, A, S and D
+        let bindings = self.controls.bindings();
+        for (i, (action, key)) in bindings.iter().enumerate() {
+            if players >= 2 && SECOND_PLAYER_KEYS.contains(key) {
+                return Err(ConfigError::invalid(
+                    &format!("controls.{}", action),
+                    &format!("uses {}, kept by player 2 with W, A, S and D", key),
+                ));
+            }
+
+            if let Some((other, _)) = bindings[..i].iter().find(|(_, other)| other == key) {
+                return Err(ConfigError::invalid(
+                    &format!("controls.{}", action),
+                    &format!("uses {}, already bound to {}", key, other),
+                ));
+            }
+        }
+
+        if !(1..=4).contains(&players) {
+            return Err(ConfigError::invalid("game.players", "must be between 1 and 4"));
+        }
+
+        if level.spawns.len() < players as usize {
+            return Err(ConfigError::invalid(
+                "game.players",
+                &format!("the level only has {} spawn points", level.spawns.len()),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::new()
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse {
+        key: Option<String>,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    Invalid {
+        key: String,
+        message: String,
+    },
+    // A top-level table left out, it has no line to point at
+    Missing {
+        key: String,
+    },
+    Level {
+        path: String,
+        error: io::Error,
+    },
+}
+
+impl ConfigError {
+    pub(crate) fn invalid(key: &str, message: &str) -> ConfigError {
+        ConfigError::Invalid {
+            key: String::from(key),
+            message: String::from(message),
+        }
+    }
+
+    // Locates the error span and names the key written on that line, under its table.
+    // A missing field is spanned by the table lacking it, or by nothing at all for a missing table
+    fn parse(contents: &str, error: toml::de::Error) -> ConfigError {
+        let span = error.span().unwrap_or(0..0);
+        let missing = error.message()
+            .strip_prefix("missing field `")
+            .and_then(|rest| rest.split_once('`'))
+            .map(|(name, _)| String::from(name));
+
+        if let (Some(name), true) = (&missing, span.is_empty()) {
+            return ConfigError::Missing { key: name.clone() };
+        }
+
+        let start = span.start.min(contents.len());
+        let before = &contents[..start];
+
+        let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+        let text = contents[line_start..].lines().next().unwrap_or("").trim();
+
+        let table = before[..line_start]
+            .lines()
+            .rev()
+            .find_map(|line| line.trim().strip_prefix('[').and_then(|line| line.strip_suffix(']')));
+
+        let key = match (text.strip_prefix('['), text.split_once('=')) {
+            (Some(header), _) => header.strip_suffix(']').map(|header| match &missing {
+                Some(name) => format!("{}.{}", header, name),
+                None => String::from(header),
+            }),
+            (None, Some((name, _))) => Some(match table {
+                Some(table) => format!("{}.{}", table, name.trim()),
+                None => String::from(name.trim()),
+            }),
+            (None, None) => None,
+        };
+
+        ConfigError::Parse {
+            key,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            message: error.message().trim().replace('\n', ", "),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "could not read the config: {}", e),
+            ConfigError::Parse { key, line, column, message } => {
+                write!(f, "line {}, column {}: {}", line, column, message)?;
+
+                match key {
+                    Some(key) => write!(f, " for key `{}`", key),
+                    None => Ok(()),
+                }
+            }
+            ConfigError::Invalid { key, message } => write!(f, "invalid `{}`: {}", key, message),
+            ConfigError::Missing { key } => write!(f, "missing table `{}`", key),
+            ConfigError::Level { path, error } => write!(f, "could not load the level `{}`: {}", path, error),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD: &str = "[board]\nboard_block_length = 20\nblock_size = 20\n";
+    const GAME: &str = "\n[game]\nmode = 'default'\nwall = 'solid'\nlevel = 4\n";
+
+    fn location(contents: &str) -> (usize, Option<String>) {
+        match Config::parse(contents) {
+            Err(ConfigError::Parse { line, key, .. }) => (line, key),
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn unknown_key_is_located() {
+        let contents = format!("{}color = 'red'\n{}", BOARD, GAME);
+
+        assert_eq!(location(&contents), (4, Some(String::from("board.color"))));
+    }
+
+    #[test]
+    fn wrong_type_is_located() {
+        let contents = format!("{}{}", BOARD, GAME.replace("level = 4", "level = 'fast'"));
+
+        assert_eq!(location(&contents), (8, Some(String::from("game.level"))));
+    }
+
+    #[test]
+    fn missing_field_is_located() {
+        let contents = format!("{}{}", BOARD, GAME.replace("wall = 'solid'\n", ""));
+        assert_eq!(location(&contents), (5, Some(String::from("game.wall"))));
+
+        // A missing table is named, not blamed on the first one
+        assert!(matches!(Config::parse(BOARD), Err(ConfigError::Missing { key }) if key == "game"));
+        assert!(Config::parse(&format!("{}{}", BOARD, GAME)).is_ok());
+    }
+
+    #[test]
+    fn controls_are_read_by_key_name() {
+        let controls: ControlsConfig = toml::from_str("pause = 'Space'\nscreenshot = 'F5'\nup = 'D8'").unwrap();
+
+        assert_eq!(controls.pause, Key::Space);
+        assert_eq!(controls.screenshot, Key::F5);
+        assert_eq!(controls.up, Key::D8);
+        assert!(toml::from_str::<ControlsConfig>("pause = 'Pause'").is_err());
+    }
+
+    #[test]
+    fn more_than_two_players_need_a_server() {
+        let mut config = Config::new();
+
+        config.game.players = Some(2);
+        assert!(config.validate_local().is_ok());
+
+        config.game.players = Some(3);
+        assert!(config.validate().is_ok());
+        assert!(matches!(config.validate_local(), Err(ConfigError::Invalid { key, .. }) if key == "game.players"));
+    }
+
+    #[test]
+    fn wasd_is_free_without_a_second_player() {
+        let mut config = Config::new();
+        config.controls.up = Key::W;
+        config.controls.left = Key::A;
+        config.controls.down = Key::S;
+        config.controls.right = Key::D;
+        assert!(config.validate().is_ok());
+
+        config.game.players = Some(2);
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid { key, .. }) if key == "controls.up"));
+    }
+
+    #[test]
+    fn controls_keep_clear_of_player_two_and_each_other() {
+        let mut config = Config::new();
+        assert!(config.validate().is_ok());
+
+        // A single player may use them, a second one keeps them
+        config.controls.pause = Key::W;
+        assert!(config.validate().is_ok());
+
+        config.game.players = Some(2);
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid { key, .. }) if key == "controls.pause"));
+
+        config.game.players = None;
+
+        config.controls.pause = Key::Tab;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid { key, .. }) if key == "controls.autopilot"));
+
+        // Unused by the absolute scheme
+        config.controls.pause = Key::P;
+        config.controls.turn_left = Key::A;
+        assert!(config.validate().is_ok());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::board::Board;
+use crate::snake::Point;
+
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+
+// Enemies start at least that many cells away from every head
+const SPAWN_DISTANCE: i32 = 4;
+const STEPS: [(i32, i32); 4] = [(0, -1), (0, 1), (-1, 0), (1, 0)];
+
+// Patrols go back and forth along a line and balls bounce diagonally, both turn around on anything in their way.
+// Chasers walk toward the closest head
+#[derive(PartialEq, Copy, Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnemyKind {
+    Patrol,
+    Ball,
+    Chaser,
+}
+
+impl EnemyKind {
+    // Seconds between two moves
+    pub fn interval(&self) -> f64 {
+        match self {
+            EnemyKind::Patrol => 0.3,
+            EnemyKind::Ball => 0.2,
+            EnemyKind::Chaser => 0.5,
+        }
+    }
+}
+
+impl fmt::Display for EnemyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnemyKind::Patrol => write!(f, "patrol"),
+            EnemyKind::Ball => write!(f, "ball"),
+            EnemyKind::Chaser => write!(f, "chaser"),
+        }
+    }
+}
+
+// What a head running into an enemy costs the snake
+#[derive(PartialEq, Copy, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Contact {
+    #[default]
+    Kill,
+    Shrink,
+}
+
+impl fmt::Display for Contact {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Contact::Kill => write!(f, "kill"),
+            Contact::Shrink => write!(f, "shrink"),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Enemy {
+    pub cell: Point,
+    pub kind: EnemyKind,
+    heading: (i32, i32),
+    timer: f64,
+}
+
+impl Enemy {
+    pub fn new(cell: Point, kind: EnemyKind, heading: (i32, i32)) -> Enemy {
+        Enemy {
+            cell,
+            kind,
+            heading,
+            timer: kind.interval(),
+        }
+    }
+
+    // As many of the configured enemies as there are free cells for
+    pub fn spawn(board: &Board, rng: &mut ChaCha8Rng) -> Vec<Enemy> {
+        let config = &board.config.config.enemies;
+        let kinds = [
+            (EnemyKind::Patrol, config.patrol),
+            (EnemyKind::Ball, config.ball),
+            (EnemyKind::Chaser, config.chaser),
+        ];
+
+        let mut enemies = Vec::new();
+
+        for (kind, count) in kinds.iter() {
+            for _ in 0..*count {
+                let cell = match Enemy::free_cell(board, &enemies, rng) {
+                    Some(cell) => cell,
+                    None => return enemies,
+                };
+
+                let heading = match kind {
+                    EnemyKind::Patrol => *STEPS.choose(rng).unwrap(),
+                    EnemyKind::Ball => *[(1, 1), (1, -1), (-1, 1), (-1, -1)].choose(rng).unwrap(),
+                    EnemyKind::Chaser => (0, 0),
+                };
+
+                enemies.push(Enemy::new(cell, *kind, heading));
+            }
+        }
+
+        enemies
+    }
+
+    pub fn free_cell(board: &Board, enemies: &[Enemy], rng: &mut ChaCha8Rng) -> Option<Point> {
+        let free = |cell: &Point| !board.is_taken(*cell)
+            && !enemies.iter().any(|enemy| enemy.cell == *cell)
+            && board.snakes.iter().all(|snake| {
+                let head = snake.head();
+                (cell.x - head.x).abs() + (cell.y - head.y).abs() >= SPAWN_DISTANCE
+            });
+
+        board.grid.get_random_position(rng, free)
+    }
+
+    // One move once the interval is over, heads are the only blocked cells a chaser may enter
+    pub fn update(&mut self, delta_time: f64, size: i32, blocked: &HashSet<(i32, i32)>, heads: &[(i32, i32)]) {
+        self.timer -= delta_time;
+
+        if self.timer > 0.0 {
+            return;
+        }
+
+        self.timer += self.kind.interval();
+
+        let cell = (self.cell.x, self.cell.y);
+        let free = |(dx, dy): &(i32, i32)| {
+            let (x, y) = (cell.0 + dx, cell.1 + dy);
+            x >= 0 && y >= 0 && x < size && y < size && !blocked.contains(&(x, y))
+        };
+
+        let (dx, dy) = self.heading;
+        let step = match self.kind {
+            EnemyKind::Patrol => [(dx, dy), (-dx, -dy)].iter().copied().find(free),
+            EnemyKind::Ball => [(dx, dy), (-dx, dy), (dx, -dy), (-dx, -dy)].iter().copied().find(free),
+            EnemyKind::Chaser => chase(cell, size, blocked, heads),
+        };
+
+        if let Some((dx, dy)) = step {
+            self.heading = (dx, dy);
+            self.cell.x += dx;
+            self.cell.y += dy;
+        }
+    }
+}
+
+// First step on the shortest way to the closest head
+fn chase(start: (i32, i32), size: i32, blocked: &HashSet<(i32, i32)>, heads: &[(i32, i32)]) -> Option<(i32, i32)> {
+    let mut parents: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(start);
+
+    while let Some(cell) = queue.pop_front() {
+        if cell != start && heads.contains(&cell) {
+            let mut step = cell;
+
+            while parents[&step] != start {
+                step = parents[&step];
+            }
+
+            return Some((step.0 - start.0, step.1 - start.1));
+        }
+
+        for (dx, dy) in STEPS.iter() {
+            let next = (cell.0 + dx, cell.1 + dy);
+            let open = next.0 >= 0 && next.1 >= 0 && next.0 < size && next.1 < size
+                && (!blocked.contains(&next) || heads.contains(&next));
+
+            if open && next != start && !parents.contains_key(&next) {
+                parents.insert(next, cell);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    None
+}
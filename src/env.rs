@@ -0,0 +1,429 @@
+use crate::board::Board;
+use crate::config::{Config, ConfigError, GlobalConfig};
+use crate::game_mode::Wall;
+use crate::level::Level;
+use crate::simulation::{Simulation, StepOutcome};
+use crate::snake::{Direction, Point};
+use crate::snapshot::BoardSnapshot;
+
+use std::sync::Arc;
+
+
+// Up, up-right, right, down-right, down, down-left, left, up-left
+const RAYS: [(i32, i32); 8] = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum ObservationKind {
+    Grid,
+    Rays,
+    Snapshot,
+}
+
+pub enum Observation {
+    Grid(GridObservation),
+    Rays(Vec<f32>),
+    Snapshot(BoardSnapshot),
+}
+
+// Channels are body, head, food, gates and walls with the enemies, each one a row-major size x size plane
+pub struct GridObservation {
+    pub channels: usize,
+    pub size: usize,
+    pub data: Vec<f32>,
+}
+
+#[derive(Copy, Clone)]
+pub struct StepInfo {
+    pub score: u32,
+    pub length: usize,
+    pub steps: u64,
+    pub ate_food: bool,
+    pub cleared: bool,
+}
+
+pub trait Reward {
+    fn reset(&mut self, _board: &Board) {}
+
+    fn reward(&mut self, board: &Board, outcome: &StepOutcome) -> f64;
+}
+
+pub struct ShapedReward {
+    pub food: f64,
+    pub death: f64,
+    pub cleared: f64,
+    pub step: f64,
+    pub distance: f64,
+    last_distance: f64,
+}
+
+impl ShapedReward {
+    pub fn new(food: f64, death: f64, cleared: f64, step: f64, distance: f64) -> ShapedReward {
+        ShapedReward {
+            food,
+            death,
+            cleared,
+            step,
+            distance,
+            last_distance: 0.0,
+        }
+    }
+}
+
+impl Default for ShapedReward {
+    fn default() -> ShapedReward {
+        ShapedReward::new(1.0, -1.0, 10.0, 0.0, 0.0)
+    }
+}
+
+impl Reward for ShapedReward {
+    fn reset(&mut self, board: &Board) {
+        self.last_distance = food_distance(board);
+    }
+
+    fn reward(&mut self, board: &Board, outcome: &StepOutcome) -> f64 {
+        let distance = food_distance(board);
+        let mut reward = self.step;
+
+        if outcome.ate_food {
+            reward += self.food;
+        } else {
+            reward += self.distance * (self.last_distance - distance);
+        }
+
+        if outcome.died {
+            reward += self.death;
+        }
+
+        if outcome.cleared {
+            reward += self.cleared;
+        }
+
+        self.last_distance = distance;
+
+        reward
+    }
+}
+
+pub struct SnakeEnv {
+    config: Config,
+    // Loaded once, a reset only changes the seed
+    level: Level,
+    observation_kind: ObservationKind,
+    reward: Box<dyn Reward>,
+    simulation: Simulation,
+}
+
+impl SnakeEnv {
+    pub fn new(config: Config, observation_kind: ObservationKind, reward: Box<dyn Reward>) -> Result<SnakeEnv, ConfigError> {
+        let level = config.level()?;
+        config.validate_with(&level)?;
+
+        let mut env = SnakeEnv {
+            simulation: Simulation::new(Arc::new(GlobalConfig::with_level(config.clone(), level.clone())))?,
+            config,
+            level,
+            observation_kind,
+            reward,
+        };
+
+        env.reward.reset(&env.simulation.board);
+
+        Ok(env)
+    }
+
+    pub fn reset(&mut self, seed: Option<u64>) -> Observation {
+        let mut config = self.config.clone();
+        config.game.seed = seed;
+
+        // Only the seed changed, the level already built a board in new
+        self.simulation = Simulation::new(Arc::new(GlobalConfig::with_level(config, self.level.clone())))
+            .expect("the level already built a board");
+        self.reward.reset(&self.simulation.board);
+
+        self.observe()
+    }
+
+    // One action moves the head by one cell, it turns the snake at the next cell boundary
+    pub fn step(&mut self, action: Option<Direction>) -> (Observation, f64, bool, StepInfo) {
+        let mut outcome = StepOutcome::default();
+        let start_cell = self.head_cell();
+        let max_steps = 2 * (1.0 / self.simulation.board.snakes[0].frame_handler.get_move_distance()).ceil() as u32 + 2;
+
+        for index in 0..max_steps {
+            let step = self.simulation.step(&[if index == 0 { action } else { None }]);
+
+            outcome.merge(step);
+
+            if outcome.died || outcome.cleared || self.head_cell() != start_cell {
+                break;
+            }
+        }
+
+        let reward = self.reward.reward(&self.simulation.board, &outcome);
+        let info = StepInfo {
+            score: self.simulation.scores[0].scores["current"].count,
+            length: self.simulation.board.snakes[0].body.len(),
+            steps: self.simulation.steps,
+            ate_food: outcome.ate_food,
+            cleared: outcome.cleared,
+        };
+
+        (self.observe(), reward, outcome.died || outcome.cleared, info)
+    }
+
+    pub fn observe(&self) -> Observation {
+        match self.observation_kind {
+            ObservationKind::Grid => Observation::Grid(self.grid()),
+            ObservationKind::Rays => Observation::Rays(self.rays()),
+            ObservationKind::Snapshot => Observation::Snapshot(BoardSnapshot::new(&self.simulation)),
+        }
+    }
+
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    // A level file can override the configured board length
+    fn size(&self) -> i32 {
+        self.simulation.board.config.config.board.board_block_length as i32
+    }
+
+    fn head_cell(&self) -> (i32, i32) {
+        let head = self.simulation.board.snakes[0].head();
+
+        (head.x, head.y)
+    }
+
+    fn grid(&self) -> GridObservation {
+        let size = self.size() as usize;
+        let mut data = vec![0.0; 5 * size * size];
+        let board = &self.simulation.board;
+
+        let mut set = |channel: usize, point: Point| {
+            let (x, y) = (point.x as usize, point.y as usize);
+            if x < size && y < size {
+                data[channel * size * size + y * size + x] = 1.0;
+            }
+        };
+
+        for point in board.bodies().iter() {
+            set(0, *point);
+        }
+
+        set(1, board.snakes[0].head());
+        set(2, board.food.cell);
+
+        if let Some(portal) = &board.portal {
+            for gate in portal.gates.iter() {
+                set(3, gate.cell);
+            }
+        }
+
+        for wall in board.config.computed_config.level.walls.iter() {
+            set(4, *wall);
+        }
+
+        for enemy in board.enemies.iter() {
+            set(4, enemy.cell);
+        }
+
+        GridObservation {
+            channels: 5,
+            size,
+            data,
+        }
+    }
+
+    // Wall, body and food per ray as the inverse of their distance, 0 when not seen
+    fn rays(&self) -> Vec<f32> {
+        let size = self.size();
+        let board = &self.simulation.board;
+        let wall_fluid = board.game_mode.wall == Wall::Fluid;
+        let body: Vec<(i32, i32)> = board.bodies()
+            .iter()
+            .skip(1)
+            .map(|point| (point.x, point.y))
+            .collect();
+        let food = (board.food.cell.x, board.food.cell.y);
+        let head = self.head_cell();
+        let level = &board.config.computed_config.level;
+        let enemies: Vec<(i32, i32)> = board.enemies.iter().map(|enemy| (enemy.cell.x, enemy.cell.y)).collect();
+
+        let mut rays = Vec::with_capacity(RAYS.len() * 3);
+
+        for (dx, dy) in RAYS.iter() {
+            let (mut wall, mut snake, mut apple) = (0.0, 0.0, 0.0);
+            let (mut x, mut y) = head;
+
+            for distance in 1..=size {
+                x += dx;
+                y += dy;
+
+                if wall_fluid {
+                    x = x.rem_euclid(size);
+                    y = y.rem_euclid(size);
+                } else if x < 0 || y < 0 || x >= size || y >= size {
+                    wall = 1.0 / distance as f32;
+                    break;
+                }
+
+                if level.is_wall(Point { x, y }) || enemies.contains(&(x, y)) {
+                    wall = 1.0 / distance as f32;
+                    break;
+                }
+
+                if snake == 0.0 && body.contains(&(x, y)) && (x, y) != head {
+                    snake = 1.0 / distance as f32;
+                }
+
+                if apple == 0.0 && (x, y) == food {
+                    apple = 1.0 / distance as f32;
+                }
+            }
+
+            rays.push(wall);
+            rays.push(snake);
+            rays.push(apple);
+        }
+
+        rays
+    }
+}
+
+fn food_distance(board: &Board) -> f64 {
+    let size = board.config.config.board.board_block_length as i32;
+    let (head, food) = (board.snakes[0].head(), board.food.cell);
+    let (dx, dy) = ((head.x - food.x).abs(), (head.y - food.y).abs());
+
+    let distance = match board.game_mode.wall {
+        Wall::Fluid => dx.min(size - dx) + dy.min(size - dy),
+        Wall::Solid => dx + dy,
+    };
+
+    distance as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(observation_kind: ObservationKind) -> SnakeEnv {
+        let mut config = Config::new();
+        config.game.seed = Some(1);
+
+        SnakeEnv::new(config, observation_kind, Box::new(ShapedReward::default())).unwrap()
+    }
+
+    fn snapshot(observation: Observation) -> String {
+        match observation {
+            Observation::Snapshot(snapshot) => serde_json::to_string(&snapshot).unwrap(),
+            _ => panic!("expected a snapshot"),
+        }
+    }
+
+    #[test]
+    fn invalid_config_is_rejected() {
+        let mut config = Config::new();
+        config.game.level = 0;
+
+        assert!(SnakeEnv::new(config, ObservationKind::Rays, Box::new(ShapedReward::default())).is_err());
+    }
+
+    #[test]
+    fn one_action_moves_the_head_by_one_cell() {
+        let mut env = env(ObservationKind::Snapshot);
+        let actions = [None, Some(Direction::Down), None, Some(Direction::Right), Some(Direction::Up)];
+
+        for action in actions {
+            let (x, y) = env.head_cell();
+            let (_, _, done, _) = env.step(action);
+            let (next_x, next_y) = env.head_cell();
+
+            assert!(!done);
+            assert_eq!((next_x - x).abs() + (next_y - y).abs(), 1);
+        }
+    }
+
+    #[test]
+    fn reset_with_a_seed_replays_the_same_episode() {
+        let mut env = env(ObservationKind::Snapshot);
+        let actions = [Some(Direction::Down), None, Some(Direction::Right), None, Some(Direction::Up), None];
+
+        let mut episode = |seed| {
+            let mut snapshots = vec![snapshot(env.reset(Some(seed)))];
+            snapshots.extend(actions.iter().map(|action| snapshot(env.step(*action).0)));
+
+            snapshots
+        };
+
+        let first = episode(7);
+        assert!(first == episode(7));
+        assert!(first != episode(8));
+    }
+
+    #[test]
+    fn observations_have_their_shape() {
+        match env(ObservationKind::Grid).observe() {
+            Observation::Grid(grid) => {
+                assert_eq!((grid.channels, grid.size), (5, 20));
+                assert_eq!(grid.data.len(), 5 * 20 * 20);
+                assert_eq!(grid.data.iter().filter(|value| **value == 1.0).count(), 2 + 1 + 1);
+            }
+            _ => panic!("expected a grid"),
+        }
+
+        match env(ObservationKind::Rays).observe() {
+            Observation::Rays(rays) => {
+                assert_eq!(rays.len(), RAYS.len() * 3);
+                assert!(rays.iter().all(|value| (0.0..=1.0).contains(value)));
+            }
+            _ => panic!("expected rays"),
+        }
+    }
+
+    #[test]
+    fn shaped_reward_adds_each_term() {
+        let mut env = env(ObservationKind::Rays);
+        let board = &mut env.simulation.board;
+        let mut reward = ShapedReward::new(1.0, -2.0, 10.0, 0.25, 0.5);
+
+        reward.reset(board);
+        assert_eq!(reward.reward(board, &StepOutcome::default()), 0.25);
+
+        // One cell further from the food
+        let head = board.snakes[0].head();
+        board.food.cell = Point { x: head.x, y: head.y + 5 };
+        reward.reset(board);
+        board.food.cell = Point { x: head.x, y: head.y + 6 };
+        assert_eq!(reward.reward(board, &StepOutcome::default()), 0.25 - 0.5);
+
+        let outcome = StepOutcome { ate_food: true, ..StepOutcome::default() };
+        assert_eq!(reward.reward(board, &outcome), 0.25 + 1.0);
+
+        let outcome = StepOutcome { died: true, ..StepOutcome::default() };
+        assert_eq!(reward.reward(board, &outcome), 0.25 - 2.0);
+
+        let outcome = StepOutcome { ate_food: true, cleared: true, ..StepOutcome::default() };
+        assert_eq!(reward.reward(board, &outcome), 0.25 + 1.0 + 10.0);
+    }
+
+    #[test]
+    fn running_into_the_wall_ends_the_episode() {
+        let mut env = env(ObservationKind::Rays);
+
+        // The snake heads right along its row to the solid wall
+        for _ in 0..env.size() {
+            let (_, reward, done, info) = env.step(None);
+
+            if done {
+                assert!(!env.simulation().dead.is_empty());
+                assert!(reward < 0.0);
+                assert!(!info.cleared);
+
+                return;
+            }
+        }
+
+        panic!("the snake never reached the wall");
+    }
+}
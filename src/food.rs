@@ -1,14 +1,151 @@
+use crate::config::FoodConfig;
+use crate::snake::Point;
+
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+
+// Seconds of boost after a speed food, and the extra moves it adds per frame
+pub const SPEED_DURATION: f64 = 5.0;
+pub const SPEED_BOOST: f64 = 0.5;
+
+#[derive(PartialEq, Copy, Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FoodKind {
+    Normal,
+    Golden,
+    Poison,
+    Speed,
+}
+
+impl FoodKind {
+    // Only the normal food is drawn when the other kinds are disabled, old seeds keep their games
+    pub fn random(weights: &FoodConfig, rng: &mut ChaCha8Rng) -> FoodKind {
+        if weights.golden + weights.poison + weights.speed == 0 {
+            return FoodKind::Normal;
+        }
+
+        let kinds = [
+            (FoodKind::Normal, weights.normal),
+            (FoodKind::Golden, weights.golden),
+            (FoodKind::Poison, weights.poison),
+            (FoodKind::Speed, weights.speed),
+        ];
+
+        pick(&kinds, rng).unwrap_or(FoodKind::Normal)
+    }
+
+    pub fn points(&self) -> u32 {
+        match self {
+            FoodKind::Normal => 1,
+            FoodKind::Golden => 5,
+            FoodKind::Poison => 0,
+            FoodKind::Speed => 1,
+        }
+    }
+
+    pub fn grows(&self) -> bool {
+        *self != FoodKind::Poison
+    }
+
+    // Seconds before the food moves elsewhere
+    pub fn lifetime(&self) -> Option<f64> {
+        match self {
+            FoodKind::Golden => Some(5.0),
+            _ => None,
+        }
+    }
+}
+
+// One of the choices at random, in proportion to its weight
+pub fn pick<T: Copy>(choices: &[(T, u32)], rng: &mut ChaCha8Rng) -> Option<T> {
+    let total: u32 = choices.iter().map(|(_, weight)| weight).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut pick = rng.gen_range(0..total);
+    for (choice, weight) in choices.iter() {
+        if pick < *weight {
+            return Some(*choice);
+        }
+
+        pick -= weight;
+    }
+
+    None
+}
+
+impl fmt::Display for FoodKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FoodKind::Normal => write!(f, "normal"),
+            FoodKind::Golden => write!(f, "golden"),
+            FoodKind::Poison => write!(f, "poison"),
+            FoodKind::Speed => write!(f, "speed"),
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Food {
-    pub x: f64,
-    pub y: f64,
+    pub cell: Point,
+    pub kind: FoodKind,
+    pub lifetime: Option<f64>,
 }
 
 impl Food {
-    pub fn new() -> Food {
+    pub fn new(cell: Point, kind: FoodKind) -> Food {
         Food {
-            x: 15.0,
-            y: 15.0,
+            cell,
+            kind,
+            lifetime: kind.lifetime(),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn draws(weights: &FoodConfig, seed: u64) -> Vec<FoodKind> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+        (0..4000).map(|_| FoodKind::random(weights, &mut rng)).collect()
+    }
+
+    #[test]
+    fn kinds_are_drawn_by_weight() {
+        let weights = FoodConfig {
+            normal: 5,
+            golden: 3,
+            poison: 2,
+            speed: 0,
+        };
+
+        let kinds = draws(&weights, 7);
+        assert!(kinds == draws(&weights, 7));
+
+        let share = |kind| kinds.iter().filter(|drawn| **drawn == kind).count() as f64 / kinds.len() as f64;
+        assert!((share(FoodKind::Normal) - 0.5).abs() < 0.05);
+        assert!((share(FoodKind::Golden) - 0.3).abs() < 0.05);
+        assert!((share(FoodKind::Poison) - 0.2).abs() < 0.05);
+        assert_eq!(share(FoodKind::Speed), 0.0);
+    }
+
+    #[test]
+    fn only_normal_food_without_other_weights() {
+        let weights = FoodConfig {
+            normal: 0,
+            golden: 0,
+            poison: 0,
+            speed: 0,
+        };
+
+        assert!(draws(&weights, 7).iter().all(|kind| *kind == FoodKind::Normal));
+        assert_eq!(pick::<u32>(&[(1, 0), (2, 0)], &mut ChaCha8Rng::seed_from_u64(7)), None);
+    }
+}
@@ -1,19 +1,66 @@
 use crate::config::GlobalConfig;
 
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Copy, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Mode {
     Default,
     Portal,
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Copy, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Wall {
     Fluid,
     Solid,
 }
 
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mode::Default => write!(f, "default"),
+            Mode::Portal => write!(f, "portal"),
+        }
+    }
+}
+
+impl fmt::Display for Wall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Wall::Fluid => write!(f, "fluid"),
+            Wall::Solid => write!(f, "solid"),
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Mode, String> {
+        match mode {
+            "default" => Ok(Mode::Default),
+            "portal" => Ok(Mode::Portal),
+            _ => Err(String::from("mode must be default or portal")),
+        }
+    }
+}
+
+impl FromStr for Wall {
+    type Err = String;
+
+    fn from_str(wall: &str) -> Result<Wall, String> {
+        match wall {
+            "solid" => Ok(Wall::Solid),
+            "fluid" => Ok(Wall::Fluid),
+            _ => Err(String::from("wall must be solid or fluid")),
+        }
+    }
+}
+
 pub struct GameMode {
     pub mode: Mode,
     pub wall: Wall,
@@ -22,16 +69,8 @@ pub struct GameMode {
 impl GameMode {
     pub fn new(config: Arc<GlobalConfig>) -> GameMode {
         GameMode {
-            mode: match &config.config.game.mode[..] {
-                "default" => Mode::Default,
-                "portal" => Mode::Portal,
-                _ => Mode::Default
-            },
-            wall: match &config.config.game.wall[..] {
-                "solid" => Wall::Solid,
-                "fluid" => Wall::Fluid,
-                _ => Wall::Solid
-            },
+            mode: config.config.game.mode,
+            wall: config.config.game.wall,
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+
+pub struct HamiltonianCycle {
+    cells: Vec<(i32, i32)>,
+    indexes: HashMap<(i32, i32), usize>,
+}
+
+impl HamiltonianCycle {
+    // The last column is walked down, then the rows are walked in a serpentine back up.
+    // An odd size ends row 0 on the left edge, so it needs the fluid wrap to close.
+    pub fn new(size: i32, wall_fluid: bool) -> Option<HamiltonianCycle> {
+        if size < 2 || (size % 2 == 1 && !wall_fluid) {
+            return None;
+        }
+
+        let mut cells: Vec<(i32, i32)> = (0..size).map(|y| (size - 1, y)).collect();
+
+        for y in (0..size).rev() {
+            if (size - 1 - y) % 2 == 0 {
+                cells.extend((0..size - 1).rev().map(|x| (x, y)));
+            } else {
+                cells.extend((0..size - 1).map(|x| (x, y)));
+            }
+        }
+
+        let indexes = cells
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| (*cell, index))
+            .collect();
+
+        Some(HamiltonianCycle {
+            cells,
+            indexes,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn contains(&self, cell: (i32, i32)) -> bool {
+        self.indexes.contains_key(&cell)
+    }
+
+    pub fn next(&self, cell: (i32, i32)) -> (i32, i32) {
+        self.cells[(self.indexes[&cell] + 1) % self.len()]
+    }
+
+    pub fn distance(&self, from: (i32, i32), to: (i32, i32)) -> usize {
+        (self.indexes[&to] + self.len() - self.indexes[&from]) % self.len()
+    }
+}
@@ -0,0 +1,240 @@
+use crate::config::Config;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+pub const TOP_ENTRIES: usize = 10;
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct HighScoreEntry {
+    pub name: String,
+    pub score: u32,
+    pub length: u32,
+    pub duration: f64,
+    pub date: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct HighScores {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(default)]
+    tables: HashMap<String, Vec<HighScoreEntry>>,
+}
+
+impl HighScores {
+    // $XDG_DATA_HOME/snake-rust/highscores.toml on Linux
+    pub fn default_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("snake-rust")
+            .join("highscores.toml")
+    }
+
+    // No scores yet, saved to the given path
+    pub fn new(path: &Path) -> HighScores {
+        HighScores {
+            path: path.to_path_buf(),
+            tables: HashMap::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<HighScores> {
+        let mut high_scores: HighScores = match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HighScores::new(path),
+            Err(e) => return Err(e),
+        };
+
+        high_scores.path = path.to_path_buf();
+
+        Ok(high_scores)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let contents = toml::to_string(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        fs::write(&self.path, contents)
+    }
+
+    // One table per mode, wall, level, board size and level file
+    pub fn key(config: &Config) -> String {
+        let mut key = format!(
+            "{} {} level {} {}x{}",
+            config.game.mode,
+            config.game.wall,
+            config.game.level,
+            config.board.board_block_length,
+            config.board.board_block_length,
+        );
+
+        if let Some(level) = &config.board.level {
+            key.push(' ');
+            key.push_str(&Path::new(level).file_stem().unwrap_or_default().to_string_lossy());
+        }
+
+        key
+    }
+
+    pub fn table(&self, key: &str) -> &[HighScoreEntry] {
+        self.tables.get(key).map(|table| &table[..]).unwrap_or(&[])
+    }
+
+    pub fn best(&self, key: &str) -> u32 {
+        self.table(key).first().map(|entry| entry.score).unwrap_or(0)
+    }
+
+    pub fn qualifies(&self, key: &str, score: u32, duration: f64) -> bool {
+        let table = self.table(key);
+
+        score > 0 && (table.len() < TOP_ENTRIES || table.iter().any(|entry| ranks_above(score, duration, entry)))
+    }
+
+    pub fn insert(&mut self, key: &str, entry: HighScoreEntry) -> usize {
+        let table = self.tables.entry(String::from(key)).or_default();
+
+        let rank = table
+            .iter()
+            .position(|other| ranks_above(entry.score, entry.duration, other))
+            .unwrap_or(table.len());
+
+        table.insert(rank, entry);
+        table.truncate(TOP_ENTRIES);
+
+        rank
+    }
+}
+
+// Higher scores first, the faster game wins a tie
+fn ranks_above(score: u32, duration: f64, other: &HighScoreEntry) -> bool {
+    score > other.score || (score == other.score && duration < other.duration)
+}
+
+// YYYY-MM-DD in UTC
+pub fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() / 86400)
+        .unwrap_or(0) as i64;
+
+    date(days)
+}
+
+// YYYY-MM-DD from the days since the epoch
+fn date(days: i64) -> String {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "default solid level 4 20x20";
+
+    fn entry(score: u32, duration: f64) -> HighScoreEntry {
+        HighScoreEntry {
+            name: String::from("player"),
+            score,
+            length: score + 3,
+            duration,
+            date: String::from("2024-02-29"),
+        }
+    }
+
+    #[test]
+    fn dates_are_counted_from_the_epoch() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(59), "1970-03-01");
+        assert_eq!(date(11016), "2000-02-29");
+        assert_eq!(date(19782), "2024-02-29");
+        assert_eq!(date(20088), "2024-12-31");
+
+        let today = today();
+        assert_eq!(today.len(), 10);
+        assert!(today.as_str() > "2024-01-01");
+    }
+
+    #[test]
+    fn higher_scores_rank_first_and_the_faster_game_wins_a_tie() {
+        let mut high_scores = HighScores::new(Path::new("highscores.toml"));
+
+        assert_eq!(high_scores.insert(KEY, entry(5, 30.0)), 0);
+        assert_eq!(high_scores.insert(KEY, entry(8, 60.0)), 0);
+        assert_eq!(high_scores.insert(KEY, entry(5, 20.0)), 1);
+        assert_eq!(high_scores.insert(KEY, entry(5, 40.0)), 3);
+
+        let ranked: Vec<(u32, f64)> = high_scores.table(KEY).iter().map(|entry| (entry.score, entry.duration)).collect();
+        assert_eq!(ranked, vec![(8, 60.0), (5, 20.0), (5, 30.0), (5, 40.0)]);
+        assert_eq!(high_scores.best(KEY), 8);
+        assert_eq!(high_scores.best("other"), 0);
+    }
+
+    #[test]
+    fn qualifies_agrees_with_insert_on_a_full_table() {
+        let mut high_scores = HighScores::new(Path::new("highscores.toml"));
+
+        assert!(!high_scores.qualifies(KEY, 0, 1.0));
+        assert!(high_scores.qualifies(KEY, 1, 1.0));
+
+        for _ in 0..TOP_ENTRIES {
+            high_scores.insert(KEY, entry(5, 30.0));
+        }
+
+        // A tie makes the table only when it was faster
+        assert!(high_scores.qualifies(KEY, 5, 20.0));
+        assert!(!high_scores.qualifies(KEY, 5, 30.0));
+        assert!(!high_scores.qualifies(KEY, 4, 1.0));
+        assert!(high_scores.qualifies(KEY, 6, 90.0));
+
+        assert_eq!(high_scores.insert(KEY, entry(5, 20.0)), 0);
+        assert_eq!(high_scores.insert(KEY, entry(5, 30.0)), TOP_ENTRIES);
+        assert_eq!(high_scores.table(KEY).len(), TOP_ENTRIES);
+    }
+
+    #[test]
+    fn saved_scores_load_back() {
+        let directory = std::env::temp_dir().join(format!("snake-highscores-{}", std::process::id()));
+        let path = directory.join("highscores.toml");
+
+        let mut high_scores = HighScores::load(&path).unwrap();
+        assert!(high_scores.table(KEY).is_empty());
+
+        high_scores.insert(KEY, entry(7, 12.5));
+        high_scores.insert(KEY, entry(3, 4.0));
+        high_scores.save().unwrap();
+
+        let loaded = HighScores::load(&path).unwrap();
+        let entries: Vec<(u32, u32, f64, &str)> = loaded
+            .table(KEY)
+            .iter()
+            .map(|entry| (entry.score, entry.length, entry.duration, entry.date.as_str()))
+            .collect();
+        assert_eq!(entries, vec![(7, 10, 12.5, "2024-02-29"), (3, 6, 4.0, "2024-02-29")]);
+
+        fs::write(&path, "tables = 1").unwrap();
+        assert_eq!(HighScores::load(&path).err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+
+// The keys a control can be bound to, by the names of the window keys: `Up`, `W`, `D1`, `Space`, `Tab`, `F12`...
+// The game turns them into its window keys, the library never reads the keyboard
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Deserialize, Serialize)]
+pub enum Key {
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    D0, D1, D2, D3, D4, D5, D6, D7, D8, D9,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Up, Down, Left, Right,
+    Space, Return, Tab, Backspace,
+    Insert, Delete, Home, End, PageUp, PageDown,
+    LShift, RShift, LCtrl, RCtrl, LAlt, RAlt,
+    Minus, Equals, Comma, Period, Slash, Backslash, Semicolon, Quote, Backquote, LeftBracket, RightBracket,
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
@@ -0,0 +1,258 @@
+use crate::board::Grid;
+use crate::snake::{Direction, Point};
+
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::iter;
+use std::path::Path;
+
+
+// A level file is an ASCII map, optionally preceded by TOML settings and a blank line:
+// '#' wall, '>' '<' '^' 'v' spawn and direction in player order,
+// 'A' 'B' a pair of portal gates, then 'C' 'D', 'E' 'F' and 'G' 'H' for more pairs.
+// Walls are looked up on every move and every observation ray, a set keeps that O(1)
+#[derive(Clone)]
+pub struct Level {
+    pub size: u32,
+    pub walls: HashSet<Point>,
+    pub spawns: Vec<(Point, Direction)>,
+    pub length: u32,
+    pub gates: Vec<Point>,
+}
+
+#[derive(Deserialize)]
+struct LevelSettings {
+    #[serde(default = "default_length")]
+    length: u32,
+}
+
+impl Level {
+    pub fn empty(size: u32) -> Level {
+        let length = size as i32;
+
+        Level {
+            size,
+            walls: HashSet::new(),
+            // Each player starts near its own corner, heading along a different edge
+            spawns: vec![
+                (Point { x: 4, y: 4 }, Direction::Right),
+                (Point { x: length - 5, y: length - 3 }, Direction::Left),
+                (Point { x: length - 3, y: 4 }, Direction::Down),
+                (Point { x: 2, y: length - 5 }, Direction::Up),
+            ],
+            length: default_length(),
+            gates: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Level> {
+        Level::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(contents: &str) -> io::Result<Level> {
+        let contents = contents.replace("\r\n", "\n");
+
+        // Map rows never hold '=', a leading block without a `key = value` line is already the map
+        let (settings, map) = match contents.split_once("\n\n") {
+            Some((settings, map)) if settings.lines().any(|line| line.contains('=')) => (settings, map),
+            _ => ("", &contents[..]),
+        };
+
+        // Lines of the file before the map, for the error messages
+        let offset = if settings.is_empty() { 0 } else { settings.lines().count() + 1 };
+
+        let settings: LevelSettings = toml::from_str(settings)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        // Blank lines around the map are dropped, one inside would shift every row below it
+        let lines: Vec<&str> = map.lines().collect();
+        let first = lines.iter().position(|row| !row.trim().is_empty()).unwrap_or(lines.len());
+        let last = lines.iter().rposition(|row| !row.trim().is_empty()).map(|last| last + 1).unwrap_or(first);
+
+        if let Some(blank) = (first..last).find(|line| lines[*line].trim().is_empty()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: blank line inside the map", offset + blank + 1),
+            ));
+        }
+
+        let rows = &lines[first..last];
+        let width = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0);
+
+        let mut level = Level {
+            size: rows.len().max(width) as u32,
+            walls: HashSet::new(),
+            spawns: Vec::new(),
+            length: settings.length,
+            gates: Vec::new(),
+        };
+
+        let mut gates = Vec::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            for (x, cell) in row.chars().enumerate() {
+                let position = Point { x: x as i32, y: y as i32 };
+
+                match cell {
+                    '#' => {
+                        level.walls.insert(position);
+                    }
+                    '>' => level.spawns.push((position, Direction::Right)),
+                    '<' => level.spawns.push((position, Direction::Left)),
+                    '^' => level.spawns.push((position, Direction::Up)),
+                    'v' => level.spawns.push((position, Direction::Down)),
+                    'A'..='H' => gates.push((cell, position)),
+                    _ => {}
+                }
+            }
+        }
+
+        if level.spawns.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the level has no spawn point"));
+        }
+
+        // The whole starting body lies behind the spawn, on the board and off the walls
+        for (spawn, direction) in &level.spawns {
+            let size = level.size as i32;
+            let blocked = level.body(*spawn, *direction)
+                .any(|cell| cell.x < 0 || cell.y < 0 || cell.x >= size || cell.y >= size || level.is_wall(cell));
+
+            if blocked {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "line {}, column {}: the snake spawned there runs into a wall or off the board",
+                        offset + first + spawn.y as usize + 1,
+                        spawn.x + 1,
+                    ),
+                ));
+            }
+        }
+
+        // Each letter once, pairs in order from A B, so gates[2n] and gates[2n + 1] are linked
+        gates.sort_by_key(|(letter, _)| *letter);
+
+        let letters: String = gates.iter().map(|(letter, _)| *letter).collect();
+        if !"ABCDEFGH".starts_with(&letters) || !letters.len().is_multiple_of(2) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the level gates must come in pairs A B, C D, E F, G H, each letter once",
+            ));
+        }
+
+        level.gates = gates.into_iter().map(|(_, position)| position).collect();
+
+        if level.free_cells() == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the level has no free cell left for the food"));
+        }
+
+        Ok(level)
+    }
+
+    // Cells of the board grid neither walls nor under a snake at the start, with every spawn taken
+    fn free_cells(&self) -> usize {
+        let mut grid = Grid::new(self.size as i32, &self.walls);
+
+        for (spawn, direction) in &self.spawns {
+            for cell in self.body(*spawn, *direction) {
+                grid.occupy(cell);
+            }
+        }
+
+        grid.free_cells()
+    }
+
+    // Cells of a starting snake, from the head back
+    fn body(&self, spawn: Point, direction: Direction) -> impl Iterator<Item = Point> {
+        iter::successors(Some(spawn), move |cell| Some(cell.moved(direction.opposite()))).take(self.length.max(1) as usize)
+    }
+
+    pub fn is_wall(&self, cell: Point) -> bool {
+        self.walls.contains(&cell)
+    }
+}
+
+fn default_length() -> u32 {
+    2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_with_blank_lines_has_no_settings() {
+        let level = Level::parse("####\n.>.#\n####\n\n").unwrap();
+        assert_eq!(level.size, 4);
+        assert_eq!(level.walls.len(), 9);
+
+        let error = Level::parse("####\n#>.#\n\n#..#\n####\n").err().unwrap();
+        assert_eq!(error.to_string(), "line 3: blank line inside the map");
+    }
+
+    #[test]
+    fn blank_lines_around_the_map_are_dropped() {
+        let level = Level::parse("length = 1\n\n\n####\n#>.#\n####\n\n").unwrap();
+        assert!(level.spawns == vec![(Point { x: 1, y: 1 }, Direction::Right)]);
+
+        let error = Level::parse("length = 1\n\n####\n#>.#\n\n####\n").err().unwrap();
+        assert_eq!(error.to_string(), "line 5: blank line inside the map");
+    }
+
+    #[test]
+    fn spawn_body_into_a_wall_or_off_the_board_is_rejected() {
+        let error = Level::parse("#####\n#>..#\n#####\n").err().unwrap();
+        assert_eq!(error.to_string(), "line 2, column 2: the snake spawned there runs into a wall or off the board");
+
+        assert!(Level::parse("length = 3\n\n.....\n.>...\n.....\n").is_err());
+        assert!(Level::parse(".....\n.>...\n.....\n").is_ok());
+        assert!(Level::parse("length = 1\n\n#####\n#>..#\n#####\n").is_ok());
+    }
+
+    #[test]
+    fn settings_come_before_the_map() {
+        let level = Level::parse("length = 1\n\n####\n#>.#\n####\n").unwrap();
+        assert_eq!(level.length, 1);
+        assert_eq!(level.spawns.len(), 1);
+    }
+
+    // Every free cell of the shipped levels can be walked to from the first spawn, gates aside
+    #[test]
+    fn shipped_levels_are_walkable() {
+        for name in ["box", "portals", "rooms"] {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("levels").join(name).with_extension("txt");
+            let level = Level::load(&path).unwrap();
+            let size = level.size as i32;
+
+            let open = |cell: &Point| cell.x >= 0 && cell.y >= 0 && cell.x < size && cell.y < size && !level.is_wall(*cell);
+            let mut reached = HashSet::from([level.spawns[0].0]);
+            let mut queue = vec![level.spawns[0].0];
+
+            while let Some(cell) = queue.pop() {
+                for direction in [Direction::Up, Direction::Down, Direction::Left, Direction::Right] {
+                    let next = cell.moved(direction);
+
+                    if open(&next) && reached.insert(next) {
+                        queue.push(next);
+                    }
+                }
+            }
+
+            let cells = (0..size).flat_map(|y| (0..size).map(move |x| Point { x, y }));
+            assert_eq!(cells.filter(open).count(), reached.len(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn level_without_a_free_cell_is_rejected() {
+        assert!(Level::parse("###\n#>#\n###\n").is_err());
+        assert!(Level::parse("####\n#.>#\n#<.#\n####\n").is_err());
+        assert!(Level::parse("length = 1\n\n####\n#.>#\n#<.#\n####\n").is_ok());
+        assert!(Level::parse("length = 1\n\n###\n#>#\n###\n").is_err());
+
+        // The edge rows are played as any other
+        assert!(Level::parse("length = 1\n\n#.#\n#>#\n###\n").is_ok());
+    }
+}
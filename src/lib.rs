@@ -0,0 +1,23 @@
+pub mod autopilot;
+pub mod board;
+pub mod clock;
+pub mod config;
+pub mod enemy;
+pub mod env;
+pub mod food;
+pub mod game_mode;
+pub mod hamiltonian;
+pub mod highscore;
+pub mod key;
+pub mod level;
+pub mod network;
+pub mod portal;
+pub mod power_up;
+pub mod replay;
+pub mod score;
+pub mod simulation;
+pub mod snake;
+pub mod snapshot;
+
+#[cfg(feature = "python")]
+pub mod python;
@@ -1,61 +1,264 @@
-mod board;
 mod board_controller;
 mod board_view;
-mod config;
-mod food;
-mod game_mode;
-mod portal;
-mod score;
-mod snake;
-
-use crate::board::Board;
-use crate::board_controller::BoardController;
+mod cli;
+mod tui;
+
+use crate::board_controller::{key_direction, second_player_direction, BoardController, ReplayMode};
 use crate::board_view::BoardView;
-use crate::config::GlobalConfig;
-use crate::game_mode::GameMode;
-use crate::score::Score;
+use crate::cli::{Cli, Command};
+use snake_rust::config::{Config, ConfigError, GlobalConfig};
+use snake_rust::highscore::HighScores;
+use snake_rust::level::Level;
+use snake_rust::network::{Client, Server, DEFAULT_ADDRESS};
+use snake_rust::replay::{Replay, ReplayPlayer};
+use snake_rust::snake::Direction;
 
+use clap::Parser;
 use piston_window::*;
-use std::path::Path;
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::sync::Arc;
 
 
 fn main() {
-    let config = Arc::new(GlobalConfig::new());
-    let mut window: PistonWindow = WindowSettings::new(
-        "snake",
-        [
-            config.computed_config.board_size + config.computed_config.score_size,
-            config.computed_config.board_size
-        ],
-    ).exit_on_esc(true).resizable(false).build().unwrap();
+    let cli = Cli::parse();
 
-    let board = Board::new(
-        config.clone(),
-        Arc::new(GameMode::new(config.clone())),
-        None,
-    );
+    match &cli.command {
+        Some(Command::Server { address }) => serve(&cli, address.as_deref().unwrap_or(DEFAULT_ADDRESS)),
+        Some(Command::Connect { address }) => connect(&cli, address),
+        None => play(&cli),
+    }
+}
+
+fn play(cli: &Cli) {
+    let replay = cli.replay.as_ref().map(|path| Replay::load(path).unwrap_or_else(|e| replay_error(path, e)));
+
+    let config = match (&replay, &cli.replay) {
+        (Some(replay), Some(path)) => Arc::new(replay.global_config().unwrap_or_else(|e| replay_error(path, e))),
+        _ => global_config(&config_path(cli), local_config(cli)),
+    };
+
+    let replay_mode = match (replay, &cli.record) {
+        (Some(replay), _) => ReplayMode::Playback(ReplayPlayer::new(replay)),
+        (None, Some(path)) => {
+            // An empty replay is written right away, an unwritable path stops the game before it starts
+            let replay = Replay::new(&config).unwrap_or_else(|e| replay_error(path, e));
+            replay.save(path).unwrap_or_else(|e| replay_error(path, e));
+
+            ReplayMode::Record(replay, path.clone())
+        }
+        (None, None) => ReplayMode::Off,
+    };
+
+    let high_scores_path = HighScores::default_path();
+    let high_scores = HighScores::load(&high_scores_path).unwrap_or_else(|e| {
+        eprintln!("Could not load the high scores: {}", e);
+        HighScores::new(&high_scores_path)
+    });
+
+    // A replay brings its own level
+    let source = cli.replay.clone().unwrap_or_else(|| config_path(cli));
+    let mut board_controller = BoardController::new(config.clone(), replay_mode, high_scores)
+        .unwrap_or_else(|e| config_error(&source, e));
+
+    if cli.tui {
+        // The rounds played so far are kept before stopping
+        if let Err(e) = tui::run(&mut board_controller) {
+            board_controller.save_replay();
+            terminal_error(e);
+        }
+    } else {
+        play_window(&config, &mut board_controller);
+    }
+
+    board_controller.save_replay();
+}
 
-    let mut board_controller = BoardController::new(board, Score::new());
+fn play_window(config: &Arc<GlobalConfig>, board_controller: &mut BoardController) {
+    let mut window = new_window(config);
 
     let mut board_view = BoardView::new(
         config.clone(),
-        window.load_font(Path::new("./assets/lcd-solid.ttf")).unwrap(),
+        window.load_font(locate("assets/lcd-solid.ttf")).unwrap(),
     );
 
-    let mut frame_delta = 0.0;
     while let Some(event) = &window.next() {
         board_controller.event(event);
 
         if let Some(args) = event.render_args() {
-            frame_delta += args.ext_dt;
-            if frame_delta < 1.0 / &config.computed_config.fps {
-                continue;
+            window.draw_2d(event, |context, graphics, device| {
+                board_view.draw(board_controller, &context, graphics, device, &args)
+            });
+
+            if board_controller.screenshot {
+                board_controller.screenshot = false;
+
+                match board_view::screenshot(&mut window.device, args.draw_size) {
+                    Ok(path) => println!("Screenshot saved to {}", path.display()),
+                    Err(e) => eprintln!("Could not save the screenshot: {}", e),
+                }
+            }
+        }
+    }
+}
+
+fn serve(cli: &Cli, address: &str) {
+    let config = global_config(&config_path(cli), load_config(cli));
+
+    let mut server = Server::bind(address, config.clone()).unwrap_or_else(|e| network_error(address, e));
+    let local_address = server.local_addr().unwrap_or_else(|e| network_error(address, e));
+
+    println!("Listening on {} for {} players", local_address, config.computed_config.players);
+
+    server.run().unwrap_or_else(|e| network_error(address, e));
+}
+
+// The board comes from the server config, the keys from the local one
+fn connect(cli: &Cli, address: &str) {
+    let controls = load_config(cli).controls;
+
+    let mut client = Client::connect(address).unwrap_or_else(|e| network_error(address, e));
+
+    // The server already sized the board from its level, the walls come with each state
+    let mut config = client.config.clone();
+    config.board.level = None;
+
+    let level = Level::empty(config.board.board_block_length);
+    let config = Arc::new(GlobalConfig::with_level(config, level));
+
+    let mut window = new_window(&config);
+
+    let mut board_view = BoardView::new(
+        config.clone(),
+        window.load_font(locate("assets/lcd-solid.ttf")).unwrap(),
+    );
+
+    let player_banner = format!("Player {}", client.player + 1);
+
+    while let Some(event) = &window.next() {
+        if let Some(Button::Keyboard(key)) = event.press_args() {
+            let heading = client
+                .state()
+                .and_then(|state| state.snapshot.snakes.get(client.player).map(|snake| snake.direction))
+                .unwrap_or(Direction::Up);
+
+            if let Some(direction) = key_direction(&controls, key, heading).or_else(|| second_player_direction(key)) {
+                client.send(direction).ok();
             }
+        }
+
+        if event.render_args().is_some() {
+            let state = match client.state() {
+                Some(state) => state,
+                None => continue,
+            };
+
+            let banner = match state.waiting {
+                _ if !client.is_connected() => "Disconnected",
+                0 => &player_banner,
+                _ => "Waiting",
+            };
 
             window.draw_2d(event, |context, graphics, device| {
-                board_view.draw(&mut board_controller, &context, graphics, device, &args)
+                board_view.draw_snapshot(&state.snapshot, &state.scores, Some(banner), &context, graphics, device)
             });
         }
     }
-}
\ No newline at end of file
+}
+
+fn new_window(config: &GlobalConfig) -> PistonWindow {
+    WindowSettings::new(
+        "snake",
+        [
+            config.computed_config.board_size + config.computed_config.score_size,
+            config.computed_config.board_size
+        ],
+    ).exit_on_esc(true).resizable(false).build().unwrap()
+}
+
+fn config_path(cli: &Cli) -> PathBuf {
+    cli.config.clone().unwrap_or_else(|| locate("Config.toml"))
+}
+
+// The config file with the command line flags on top, an invalid config stops the game.
+// Without a Config.toml the defaults are used, a path given on the command line has to exist
+fn load_config(cli: &Cli) -> Config {
+    let path = config_path(cli);
+
+    let mut config = match Config::load(&path) {
+        Err(ConfigError::Io(e)) if e.kind() == io::ErrorKind::NotFound && cli.config.is_none() => Config::new(),
+        result => result.unwrap_or_else(|e| config_error(&path, e)),
+    };
+
+    if let Err(e) = cli.apply(&mut config).and_then(|_| config.validate()) {
+        config_error(&path, e);
+    }
+
+    config
+}
+
+// Players past the second one have no keys here, they only join a server
+fn local_config(cli: &Cli) -> Config {
+    let config = load_config(cli);
+
+    if let Err(e) = config.validate_local() {
+        config_error(&config_path(cli), e);
+    }
+
+    config
+}
+
+fn global_config(path: &Path, config: Config) -> Arc<GlobalConfig> {
+    Arc::new(GlobalConfig::from_config(config).unwrap_or_else(|e| config_error(path, e)))
+}
+
+fn config_error(path: &Path, error: ConfigError) -> ! {
+    eprintln!("{}: {}", path.display(), error);
+    process::exit(1)
+}
+
+fn replay_error(path: &Path, error: io::Error) -> ! {
+    eprintln!("{}: {}", path.display(), error);
+    process::exit(1)
+}
+
+// No tty on stdin or no raw mode, the terminal frontend cannot run
+fn terminal_error(error: io::Error) -> ! {
+    eprintln!("Could not use the terminal: {}", error);
+    process::exit(1)
+}
+
+// A busy port, an absent or a full server stops the game with the address it was given
+fn network_error(address: &str, error: io::Error) -> ! {
+    eprintln!("{}: {}", address, error);
+    process::exit(1)
+}
+
+// Looks in the working directory first, then next to the executable.
+// Debug builds also look in the crate sources, a release binary does not know where it was built
+fn locate(name: &str) -> PathBuf {
+    let directories = [
+        env::current_dir().ok(),
+        env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)),
+        source_directory(),
+    ];
+
+    directories
+        .iter()
+        .flatten()
+        .map(|directory| directory.join(name))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+#[cfg(debug_assertions)]
+fn source_directory() -> Option<PathBuf> {
+    Some(PathBuf::from(env!("CARGO_MANIFEST_DIR")))
+}
+
+#[cfg(not(debug_assertions))]
+fn source_directory() -> Option<PathBuf> {
+    None
+}
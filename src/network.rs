@@ -0,0 +1,417 @@
+use crate::clock::Clock;
+use crate::config::{Config, GlobalConfig};
+use crate::score::Score;
+use crate::simulation::Simulation;
+use crate::snake::{Direction, TURN_QUEUE};
+use crate::snapshot::BoardSnapshot;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7878";
+// A client that stops reading fills its socket buffer, its writer gives up and the player is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+// States queued for a slow client, past that it misses the older ones and gets the next
+const STATE_BACKLOG: usize = 4;
+
+#[derive(Deserialize, Serialize)]
+pub enum ClientMessage {
+    Input(Direction),
+}
+
+#[derive(Deserialize, Serialize)]
+pub enum ServerMessage {
+    Welcome { player: usize, config: Config },
+    Full,
+    State(ServerState),
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ServerState {
+    pub snapshot: BoardSnapshot,
+    pub scores: Vec<Score>,
+    pub waiting: usize,
+}
+
+enum Event {
+    Input(u64, Direction),
+    Left(u64),
+}
+
+// Each client has its own writer thread, a slow one never holds up the tick
+struct Connection {
+    id: u64,
+    stream: TcpStream,
+    states: SyncSender<Arc<Vec<u8>>>,
+}
+
+pub struct Server {
+    listener: TcpListener,
+    simulation: Simulation,
+    connections: Vec<Option<Connection>>,
+    // Directions received and not played yet, one per player and tick
+    inputs: Vec<VecDeque<Direction>>,
+    next_id: u64,
+    sender: Sender<Event>,
+    events: Receiver<Event>,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(address: A, config: Arc<GlobalConfig>) -> io::Result<Server> {
+        let simulation = Simulation::new(config.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        let (sender, events) = mpsc::channel();
+
+        Ok(Server {
+            listener,
+            connections: (0..config.computed_config.players).map(|_| None).collect(),
+            inputs: vec![VecDeque::new(); config.computed_config.players],
+            simulation,
+            next_id: 0,
+            sender,
+            events,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut clock = Clock::new(&self.simulation.board.config);
+        let mut last_tick = Instant::now();
+
+        // A slow tick is caught up on the next loop, the board keeps its pace
+        loop {
+            let now = Instant::now();
+            clock.add((now - last_tick).as_secs_f64());
+            last_tick = now;
+
+            while clock.tick() {
+                self.tick()?;
+            }
+
+            thread::sleep(Duration::from_secs_f64(clock.remaining()));
+        }
+    }
+
+    // The board only moves once every player slot is taken
+    pub fn tick(&mut self) -> io::Result<()> {
+        self.accept();
+
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Event::Input(id, direction) => {
+                    if let Some(input) = self.player(id).map(|player| &mut self.inputs[player]).filter(|input| input.len() < TURN_QUEUE) {
+                        input.push_back(direction);
+                    }
+                }
+                Event::Left(id) => {
+                    if let Some(player) = self.player(id) {
+                        self.leave(player);
+                    }
+                }
+            }
+        }
+
+        let waiting = self.connections.iter().filter(|connection| connection.is_none()).count();
+
+        if waiting == 0 {
+            let inputs: Vec<Option<Direction>> = self.inputs.iter_mut().map(|input| input.pop_front()).collect();
+            let outcome = self.simulation.step(&inputs);
+
+            if outcome.died || outcome.cleared {
+                self.simulation.restart();
+            }
+        }
+
+        let message = Arc::new(encode(&ServerMessage::State(ServerState {
+            snapshot: BoardSnapshot::new(&self.simulation),
+            scores: self.simulation.scores.clone(),
+            waiting,
+        }))?);
+
+        for player in 0..self.connections.len() {
+            let sent = match self.connections[player].as_ref() {
+                Some(connection) => !matches!(
+                    connection.states.try_send(Arc::clone(&message)),
+                    Err(TrySendError::Disconnected(_))
+                ),
+                None => true,
+            };
+
+            if !sent {
+                self.leave(player);
+            }
+        }
+
+        Ok(())
+    }
+
+    // A connection that fails to set up is dropped alone, the game goes on for the others.
+    // A failed accept, such as an aborted connection or no file descriptor left, is tried again on the next tick
+    fn accept(&mut self) {
+        loop {
+            let (stream, address) = match self.listener.accept() {
+                Ok(connection) => connection,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    eprintln!("Could not accept a player: {}", e);
+                    return;
+                }
+            };
+
+            if let Err(e) = self.join(stream, address) {
+                eprintln!("Dropped the connection from {}: {}", address, e);
+            }
+        }
+    }
+
+    fn join(&mut self, mut stream: TcpStream, address: SocketAddr) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+        let player = match self.connections.iter().position(|connection| connection.is_none()) {
+            Some(player) => player,
+            None => return send(&mut stream, &ServerMessage::Full),
+        };
+
+        let welcome = ServerMessage::Welcome {
+            player,
+            config: (*self.simulation.board.config.config).clone(),
+        };
+
+        send(&mut stream, &welcome)?;
+
+        let reader = BufReader::new(stream.try_clone()?);
+        let writer = stream.try_clone()?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let sender = self.sender.clone();
+        thread::spawn(move || listen(id, reader, sender));
+
+        let (states, queue) = mpsc::sync_channel(STATE_BACKLOG);
+        let sender = self.sender.clone();
+        thread::spawn(move || write(id, writer, queue, sender));
+
+        self.connections[player] = Some(Connection { id, stream, states });
+        println!("Player {} joined from {}", player + 1, address);
+
+        // A new round starts for every player whenever someone joins
+        self.simulation.restart();
+
+        Ok(())
+    }
+
+    fn player(&self, id: u64) -> Option<usize> {
+        self.connections
+            .iter()
+            .position(|connection| connection.as_ref().map(|connection| connection.id) == Some(id))
+    }
+
+    // Shutting the stream down also ends the thread reading from it, the writer stops with its queue
+    fn leave(&mut self, player: usize) {
+        if let Some(connection) = self.connections[player].take() {
+            connection.stream.shutdown(Shutdown::Both).ok();
+        }
+
+        self.inputs[player].clear();
+        println!("Player {} left", player + 1);
+    }
+}
+
+pub struct Client {
+    stream: TcpStream,
+    pub player: usize,
+    pub config: Config,
+    state: Arc<Mutex<Option<ServerState>>>,
+    connected: Arc<AtomicBool>,
+}
+
+impl Client {
+    pub fn connect<A: ToSocketAddrs>(address: A) -> io::Result<Client> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let (player, config) = match receive(&mut reader)? {
+            Some(ServerMessage::Welcome { player, config }) => (player, config),
+            Some(ServerMessage::Full) => return Err(io::Error::new(io::ErrorKind::ConnectionRefused, "the server is full")),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "expected a welcome message")),
+        };
+
+        let state = Arc::new(Mutex::new(None));
+        let connected = Arc::new(AtomicBool::new(true));
+
+        let state_clone = Arc::clone(&state);
+        let connected_clone = Arc::clone(&connected);
+
+        thread::spawn(move || {
+            while let Ok(Some(message)) = receive(&mut reader) {
+                if let ServerMessage::State(server_state) = message {
+                    *state_clone.lock().unwrap() = Some(server_state);
+                }
+            }
+
+            connected_clone.store(false, Ordering::SeqCst);
+        });
+
+        Ok(Client {
+            stream,
+            player,
+            config,
+            state,
+            connected,
+        })
+    }
+
+    pub fn send(&mut self, direction: Direction) -> io::Result<()> {
+        send(&mut self.stream, &ClientMessage::Input(direction))
+    }
+
+    pub fn state(&self) -> Option<ServerState> {
+        self.state.lock().unwrap().clone()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+}
+
+// The reader thread holds a clone of the stream, closing it lets the server see the player leave
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.stream.shutdown(Shutdown::Both).ok();
+    }
+}
+
+fn listen(id: u64, mut reader: BufReader<TcpStream>, sender: Sender<Event>) {
+    while let Ok(Some(ClientMessage::Input(direction))) = receive(&mut reader) {
+        if sender.send(Event::Input(id, direction)).is_err() {
+            return;
+        }
+    }
+
+    sender.send(Event::Left(id)).ok();
+}
+
+// A write past the timeout or on a closed stream ends the thread, the player is dropped on the next tick
+fn write(id: u64, mut stream: TcpStream, queue: Receiver<Arc<Vec<u8>>>, sender: Sender<Event>) {
+    while let Ok(message) = queue.recv() {
+        if stream.write_all(&message).is_err() {
+            sender.send(Event::Left(id)).ok();
+            return;
+        }
+    }
+}
+
+// Messages are sent as one JSON document per line
+fn encode<T: Serialize>(message: &T) -> io::Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(message)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    line.push(b'\n');
+
+    Ok(line)
+}
+
+fn send<T: Serialize>(stream: &mut TcpStream, message: &T) -> io::Result<()> {
+    stream.write_all(&encode(message)?)
+}
+
+fn receive<T: DeserializeOwned>(reader: &mut BufReader<TcpStream>) -> io::Result<Option<T>> {
+    let mut line = String::new();
+
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    serde_json::from_str(&line)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ticks the server until the condition holds, the client threads run meanwhile
+    fn tick_until(server: &mut Server, condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        while !condition() {
+            assert!(Instant::now() < deadline, "the server never got there");
+
+            server.tick().unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn a_connection_dropped_on_arrival_leaves_the_server_running() {
+        let mut config = Config::new();
+        config.game.players = Some(2);
+
+        let config = Arc::new(GlobalConfig::from_config(config).unwrap());
+        let mut server = Server::bind("127.0.0.1:0", config).unwrap();
+        let address = server.local_addr().unwrap();
+
+        let stream = TcpStream::connect(address).unwrap();
+        stream.shutdown(Shutdown::Both).unwrap();
+        drop(stream);
+
+        let connecting = thread::spawn(move || Client::connect(address));
+        tick_until(&mut server, || connecting.is_finished());
+
+        let client = connecting.join().unwrap().unwrap();
+        tick_until(&mut server, || client.state().is_some());
+
+        // The dropped connection freed its slot again
+        tick_until(&mut server, || client.state().is_some_and(|state| state.waiting == 1));
+        assert!(client.is_connected());
+    }
+
+    #[test]
+    fn a_client_steers_its_snake_on_localhost() {
+        let mut config = Config::new();
+        config.game.seed = Some(1);
+
+        let config = Arc::new(GlobalConfig::from_config(config).unwrap());
+        let mut server = Server::bind("127.0.0.1:0", config).unwrap();
+        let address = server.local_addr().unwrap();
+
+        // The welcome only comes once the server accepted, on its next tick
+        let connecting = thread::spawn(move || Client::connect(address));
+        tick_until(&mut server, || connecting.is_finished());
+
+        let mut client = connecting.join().unwrap().unwrap();
+        assert_eq!(client.player, 0);
+
+        let heading = server.simulation.board.snakes[0].direction();
+        let turn = heading.clockwise();
+
+        client.send(turn).unwrap();
+        tick_until(&mut server, || client.state().is_some_and(|state| state.snapshot.snakes[0].direction == turn));
+
+        let state = client.state().unwrap();
+        assert_eq!(state.waiting, 0);
+        assert_eq!(state.snapshot.snakes.len(), 1);
+        assert!(client.is_connected());
+    }
+}
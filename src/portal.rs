@@ -1,90 +1,171 @@
 use crate::board::Board;
+use crate::snake::Point;
+
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// What a pair of gates does once a snake went through it
+#[derive(PartialEq, Copy, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PortalBehavior {
+    #[default]
+    Respawn,
+    Persistent,
+    OneWay,
+}
+
+impl fmt::Display for PortalBehavior {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PortalBehavior::Respawn => write!(f, "respawn"),
+            PortalBehavior::Persistent => write!(f, "persistent"),
+            PortalBehavior::OneWay => write!(f, "one_way"),
+        }
+    }
+}
 
-use crossbeam_utils::thread;
-use std::sync::{Arc, Mutex};
+// The heading of the snake coming out of a gate, rotate is a clockwise quarter turn
+#[derive(PartialEq, Copy, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortalExit {
+    #[default]
+    Keep,
+    Rotate,
+}
+
+impl fmt::Display for PortalExit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PortalExit::Keep => write!(f, "keep"),
+            PortalExit::Rotate => write!(f, "rotate"),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Gate {
-    pub x: f64,
-    pub y: f64,
+    pub cell: Point,
     pub used: bool,
 }
 
+impl Gate {
+    fn new(cell: Point) -> Gate {
+        Gate { cell, used: false }
+    }
+}
+
+// Gates go by pairs: gates[2n] and gates[2n + 1] lead to each other
 #[derive(Clone)]
 pub struct Portal {
-    pub gates: Vec<Arc<Mutex<Gate>>>,
+    pub gates: Vec<Gate>,
 }
 
 impl Portal {
-    pub fn new(board: &Board) -> Portal {
-        let local_self = Arc::new(board);
-
-        let gates: Arc<Mutex<Vec<Option<Gate>>>> = Arc::new(Mutex::new(Vec::new()));
-        let gates_clone = Arc::clone(&gates);
-
-        thread::scope(|s| {
-            s.spawn(move |_| {
-                while gates_clone.lock().unwrap().len() < 2 {
-                    let grid = local_self
-                        .grid
-                        .clone()
-                        .remove_occupied_positions(
-                            local_self.snake.body.clone(),
-                            &local_self.food,
-                            Some(gates_clone.lock().unwrap().clone()),
-                        );
-
-                    if gates_clone.lock().unwrap().len() == 0 {
-                        let (x, y) = grid.get_random_position();
-                        gates_clone.lock().unwrap().push(Some(Gate { x, y, used: false }));
-
-                        continue;
-                    }
-
-                    let other_gates_clone = Arc::clone(&gates_clone);
-                    let gates = other_gates_clone.lock().unwrap().last().cloned();
-                    let last = gates.as_ref().unwrap().as_ref().unwrap();
-
-                    'inner: for i in 0..3 {
-                        if other_gates_clone.lock().unwrap().len() == 2 {
-                            break 'inner;
-                        }
-
-                        let (new_x, new_y) = grid.get_random_position();
-
-                        if i == 2 {
-                            other_gates_clone.lock().unwrap().push(Some(Gate { x: new_x, y: new_y, used: false }));
-                            break 'inner;
-                        }
-
-                        if (last.x.abs() + last.y.abs()) - (new_x.abs() + new_y.abs()) < (board.config.config.board.board_block_length as f64 / 2.0).abs().ceil() {
-                            continue;
-                        }
-
-                        other_gates_clone.lock().unwrap().push(Some(Gate { x: new_x, y: new_y, used: false }));
-                    }
-                }
+    pub fn new(board: &Board, rng: &mut ChaCha8Rng) -> Option<Portal> {
+        let fixed_gates = &board.config.computed_config.level.gates;
+
+        // Gates fixed by the level always come back at the same place
+        if !fixed_gates.is_empty() {
+            return Some(Portal {
+                gates: fixed_gates
+                    .iter()
+                    .map(|position| Gate::new(*position))
+                    .collect()
             });
-        }).unwrap();
-
-        Portal {
-            gates: gates
-                .clone()
-                .lock()
-                .unwrap()
-                .drain(..)
-                .map(|gate| Arc::new(Mutex::new(gate.unwrap().clone())))
-                .collect()
         }
+
+        let mut gates = Vec::new();
+
+        for _ in 0..board.config.config.portals.pairs {
+            match Portal::random_pair(board, &gates, rng) {
+                Some((a, b)) => gates.extend([a, b]),
+                None => break,
+            }
+        }
+
+        if gates.is_empty() {
+            return None;
+        }
+
+        Some(Portal { gates })
     }
 
-    pub fn is_used(&self, mut portal: Option<Portal>) -> bool {
-        portal
-            .as_mut()
-            .unwrap()
-            .gates
+    // The second gate gets three tries to land far enough from the first one
+    fn random_pair(board: &Board, taken: &[Gate], rng: &mut ChaCha8Rng) -> Option<(Gate, Gate)> {
+        let free = |taken: &[Gate], cell: Point| !board.is_taken(cell)
+            && !taken.iter().any(|gate| gate.cell == cell);
+
+        let first = Gate::new(board.grid.get_random_position(rng, |cell| free(taken, *cell))?);
+
+        let mut taken = taken.to_vec();
+        taken.push(first.clone());
+
+        let min_distance = (board.config.config.board.board_block_length as f64 / 2.0).ceil() as i32;
+
+        for i in 0..3 {
+            let cell = board.grid.get_random_position(rng, |cell| free(&taken, *cell))?;
+
+            if i == 2 || (first.cell.x.abs() + first.cell.y.abs()) - (cell.x.abs() + cell.y.abs()) >= min_distance {
+                return Some((first, Gate::new(cell)));
+            }
+        }
+
+        None
+    }
+
+    pub fn is_used(&self, pair: usize) -> bool {
+        self.gates[2 * pair..2 * pair + 2].iter().any(|gate| gate.used)
+    }
+
+    // The gate on that cell when its pair is open, a one-way pair is only entered by its first gate
+    pub fn entry(&self, cell: Point, one_way: bool) -> Option<usize> {
+        self.gates
             .iter()
-            .filter(|gate| gate.lock().unwrap().used)
-            .count() > 0
+            .position(|gate| gate.cell == cell)
+            .filter(|gate| !one_way || gate % 2 == 0)
+            .filter(|gate| !self.is_used(gate / 2))
     }
-}
\ No newline at end of file
+
+    // Closes the pair until the snake is through and returns the gate it comes out of
+    pub fn take(&mut self, entry: usize) -> Gate {
+        self.gates[entry].used = true;
+
+        self.gates[entry ^ 1].clone()
+    }
+
+    // Once the tail left the exit the pair opens again, a respawning pair moves elsewhere first.
+    // It stays in place when the board has no room left for it
+    pub fn release(board: &mut Board, pair: usize, rng: &mut ChaCha8Rng) {
+        let mut portal = match board.portal.take() {
+            Some(portal) => portal,
+            None => return,
+        };
+
+        let respawn = board.config.config.portals.behavior == PortalBehavior::Respawn
+            && board.config.computed_config.level.gates.is_empty();
+
+        let others: Vec<Gate> = portal.gates
+            .iter()
+            .enumerate()
+            .filter(|(gate, _)| gate / 2 != pair)
+            .map(|(_, gate)| gate.clone())
+            .collect();
+
+        let moved = if respawn { Portal::random_pair(board, &others, rng) } else { None };
+
+        match moved {
+            Some((a, b)) => {
+                portal.gates[2 * pair] = a;
+                portal.gates[2 * pair + 1] = b;
+            }
+            None => {
+                for gate in portal.gates[2 * pair..2 * pair + 2].iter_mut() {
+                    gate.used = false;
+                }
+            }
+        }
+
+        board.portal = Some(portal);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::config::PowerUpConfig;
+use crate::food::pick;
+use crate::snake::Point;
+
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+
+// Seconds a power-up waits on the board before it disappears
+pub const POWER_UP_LIFETIME: f64 = 8.0;
+// The game runs at half speed in slow motion
+pub const SLOW_FACTOR: f64 = 0.5;
+pub const SCORE_MULTIPLIER: u32 = 2;
+// Seconds between two pulls of the magnet, the food moves one cell toward the head
+pub const MAGNET_INTERVAL: f64 = 0.25;
+
+#[derive(PartialEq, Copy, Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerUpKind {
+    Ghost,
+    Slow,
+    Magnet,
+    Shield,
+    Multiplier,
+}
+
+impl PowerUpKind {
+    pub fn random(weights: &PowerUpConfig, rng: &mut ChaCha8Rng) -> Option<PowerUpKind> {
+        let kinds = [
+            (PowerUpKind::Ghost, weights.ghost),
+            (PowerUpKind::Slow, weights.slow),
+            (PowerUpKind::Magnet, weights.magnet),
+            (PowerUpKind::Shield, weights.shield),
+            (PowerUpKind::Multiplier, weights.multiplier),
+        ];
+
+        pick(&kinds, rng)
+    }
+
+    // Seconds the effect lasts, the shield also ends on its first wall hit
+    pub fn duration(&self) -> f64 {
+        match self {
+            PowerUpKind::Ghost => 8.0,
+            PowerUpKind::Slow => 5.0,
+            PowerUpKind::Magnet => 8.0,
+            PowerUpKind::Shield => 15.0,
+            PowerUpKind::Multiplier => 10.0,
+        }
+    }
+}
+
+impl fmt::Display for PowerUpKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PowerUpKind::Ghost => write!(f, "ghost"),
+            PowerUpKind::Slow => write!(f, "slow"),
+            PowerUpKind::Magnet => write!(f, "magnet"),
+            PowerUpKind::Shield => write!(f, "shield"),
+            PowerUpKind::Multiplier => write!(f, "multiplier"),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct PowerUp {
+    pub cell: Point,
+    pub kind: PowerUpKind,
+    pub lifetime: f64,
+}
+
+impl PowerUp {
+    pub fn new(cell: Point, kind: PowerUpKind) -> PowerUp {
+        PowerUp {
+            cell,
+            kind,
+            lifetime: POWER_UP_LIFETIME,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Effect {
+    pub kind: PowerUpKind,
+    pub remaining: f64,
+}
+
+// The effects active on a snake with their remaining seconds
+#[derive(Clone, Default)]
+pub struct Effects {
+    pub active: Vec<Effect>,
+}
+
+impl Effects {
+    // Taking a power-up again restarts its timer
+    pub fn add(&mut self, kind: PowerUpKind) {
+        self.remove(kind);
+        self.active.push(Effect {
+            kind,
+            remaining: kind.duration(),
+        });
+    }
+
+    pub fn has(&self, kind: PowerUpKind) -> bool {
+        self.active.iter().any(|effect| effect.kind == kind)
+    }
+
+    pub fn remove(&mut self, kind: PowerUpKind) {
+        self.active.retain(|effect| effect.kind != kind);
+    }
+
+    pub fn tick(&mut self, dt: f64) {
+        for effect in self.active.iter_mut() {
+            effect.remaining -= dt;
+        }
+
+        self.active.retain(|effect| effect.remaining > 0.0);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::config::{AutopilotConfig, Board, Config, ControlsConfig, EnemyConfig, FoodConfig, Game, PortalConfig, PowerUpConfig};
+use crate::env::{Observation, ObservationKind, ShapedReward, SnakeEnv, StepInfo};
+use crate::game_mode::{Mode, Wall};
+use crate::snake::Direction;
+use crate::snapshot::BoardSnapshot;
+
+use numpy::{PyArray1, PyArrayMethods};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+
+
+#[pyclass(unsendable, name = "SnakeEnv")]
+pub struct PySnakeEnv {
+    env: SnakeEnv,
+}
+
+#[pymethods]
+impl PySnakeEnv {
+    #[new]
+    #[pyo3(signature = (
+        mode = "default",
+        wall = "solid",
+        board_length = 20,
+        block_size = 20,
+        level = 4,
+        level_file = None,
+        observation = "grid",
+        reward_food = 1.0,
+        reward_death = -1.0,
+        reward_cleared = 10.0,
+        reward_step = 0.0,
+        reward_distance = 0.0,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        mode: &str,
+        wall: &str,
+        board_length: u32,
+        block_size: u32,
+        level: u32,
+        level_file: Option<String>,
+        observation: &str,
+        reward_food: f64,
+        reward_death: f64,
+        reward_cleared: f64,
+        reward_step: f64,
+        reward_distance: f64,
+    ) -> PyResult<PySnakeEnv> {
+        let observation_kind = match observation {
+            "grid" => ObservationKind::Grid,
+            "rays" => ObservationKind::Rays,
+            "snapshot" => ObservationKind::Snapshot,
+            _ => return Err(PyValueError::new_err("observation must be grid, rays or snapshot")),
+        };
+
+        let mode: Mode = mode.parse().map_err(PyValueError::new_err)?;
+        let wall: Wall = wall.parse().map_err(PyValueError::new_err)?;
+
+        let config = Config {
+            board: Board {
+                board_block_length: board_length,
+                block_size,
+                level: level_file,
+            },
+            game: Game {
+                mode,
+                wall,
+                level,
+                seed: None,
+                autopilot: AutopilotConfig::Off,
+                players: None,
+                fps: None,
+                speed_up_every: None,
+                level_cap: None,
+            },
+            food: FoodConfig::default(),
+            power_ups: PowerUpConfig::default(),
+            portals: PortalConfig::default(),
+            enemies: EnemyConfig::default(),
+            controls: ControlsConfig::default(),
+        };
+
+        let reward = ShapedReward::new(reward_food, reward_death, reward_cleared, reward_step, reward_distance);
+
+        Ok(PySnakeEnv {
+            env: SnakeEnv::new(config, observation_kind, Box::new(reward)).map_err(|e| PyValueError::new_err(e.to_string()))?,
+        })
+    }
+
+    #[pyo3(signature = (seed = None))]
+    fn reset(&mut self, py: Python<'_>, seed: Option<u64>) -> PyResult<Py<PyAny>> {
+        observation_object(py, self.env.reset(seed))
+    }
+
+    // Actions are 0 up, 1 down, 2 left, 3 right, None keeps the current direction
+    #[pyo3(signature = (action = None))]
+    fn step(&mut self, py: Python<'_>, action: Option<u8>) -> PyResult<(Py<PyAny>, f64, bool, Py<PyAny>)> {
+        let direction = match action {
+            None => None,
+            Some(0) => Some(Direction::Up),
+            Some(1) => Some(Direction::Down),
+            Some(2) => Some(Direction::Left),
+            Some(3) => Some(Direction::Right),
+            Some(_) => return Err(PyValueError::new_err("action must be 0, 1, 2, 3 or None")),
+        };
+
+        let (observation, reward, done, info) = self.env.step(direction);
+
+        Ok((observation_object(py, observation)?, reward, done, info_object(py, &info)?))
+    }
+
+    fn observe(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        observation_object(py, self.env.observe())
+    }
+
+    fn snapshot(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        snapshot_object(py, &BoardSnapshot::new(self.env.simulation()))
+    }
+
+    #[getter]
+    fn score(&self) -> u32 {
+        self.env.simulation().scores[0].scores["current"].count
+    }
+
+    #[getter]
+    fn best(&self) -> u32 {
+        self.env.simulation().scores[0].scores["best"].count
+    }
+
+    #[getter]
+    fn mode(&self) -> String {
+        self.env.simulation().board.config.config.game.mode.to_string()
+    }
+
+    #[getter]
+    fn wall(&self) -> String {
+        self.env.simulation().board.config.config.game.wall.to_string()
+    }
+}
+
+fn observation_object(py: Python<'_>, observation: Observation) -> PyResult<Py<PyAny>> {
+    match observation {
+        Observation::Grid(grid) => Ok(PyArray1::from_vec(py, grid.data)
+            .reshape([grid.channels, grid.size, grid.size])?
+            .into_any()
+            .unbind()),
+        Observation::Rays(rays) => Ok(PyArray1::from_vec(py, rays).into_any().unbind()),
+        Observation::Snapshot(snapshot) => snapshot_object(py, &snapshot),
+    }
+}
+
+fn snapshot_object(py: Python<'_>, snapshot: &BoardSnapshot) -> PyResult<Py<PyAny>> {
+    let dict = PyDict::new(py);
+
+    let snakes = PyList::empty(py);
+    for snake in snapshot.snakes.iter() {
+        let snake_dict = PyDict::new(py);
+
+        snake_dict.set_item("body", snake.body.clone())?;
+        snake_dict.set_item("progress", snake.progress)?;
+        snake_dict.set_item("direction", direction_name(snake.direction))?;
+        snake_dict.set_item("score", snake.score)?;
+        snake_dict.set_item(
+            "effects",
+            snake.effects.iter().map(|(kind, remaining)| (kind.to_string(), *remaining)).collect::<Vec<_>>(),
+        )?;
+        snakes.append(snake_dict)?;
+    }
+
+    dict.set_item("size", snapshot.size)?;
+    dict.set_item("snakes", snakes)?;
+    dict.set_item("food", snapshot.food)?;
+    dict.set_item("food_kind", snapshot.food_kind.to_string())?;
+    dict.set_item("power_up", snapshot.power_up.map(|(x, y, kind)| (x, y, kind.to_string())))?;
+    dict.set_item("enemies", snapshot.enemies.iter().map(|(x, y, kind)| (*x, *y, kind.to_string())).collect::<Vec<_>>())?;
+    dict.set_item("gates", snapshot.gates.clone())?;
+    dict.set_item("walls", snapshot.walls.clone())?;
+    dict.set_item("steps", snapshot.steps)?;
+    dict.set_item("cleared", snapshot.cleared)?;
+    dict.set_item("level", snapshot.level)?;
+
+    Ok(dict.into_any().unbind())
+}
+
+fn info_object(py: Python<'_>, info: &StepInfo) -> PyResult<Py<PyAny>> {
+    let dict = PyDict::new(py);
+
+    dict.set_item("score", info.score)?;
+    dict.set_item("length", info.length)?;
+    dict.set_item("steps", info.steps)?;
+    dict.set_item("ate_food", info.ate_food)?;
+    dict.set_item("cleared", info.cleared)?;
+
+    Ok(dict.into_any().unbind())
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Up => "up",
+        Direction::Down => "down",
+        Direction::Left => "left",
+        Direction::Right => "right",
+    }
+}
+
+#[pymodule]
+fn snake_rust(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PySnakeEnv>()?;
+
+    Ok(())
+}
@@ -0,0 +1,216 @@
+use crate::config::{Config, ConfigError, GlobalConfig};
+use crate::level::Level;
+use crate::snake::Direction;
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+
+#[derive(Deserialize, Serialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub config: Config,
+    // The level map as it was played, the level file may have moved or changed since
+    #[serde(default)]
+    pub level: Option<String>,
+    pub inputs: Vec<ReplayInput>,
+    // Steps after which the player started a new round by hand
+    #[serde(default)]
+    pub restarts: Vec<u64>,
+}
+
+#[derive(Copy, Clone, Deserialize, Serialize)]
+pub struct ReplayInput {
+    pub step: u64,
+    #[serde(default)]
+    pub player: usize,
+    pub direction: Direction,
+}
+
+impl Replay {
+    // Only the file name of the level is kept next to its map, the full path may point anywhere
+    pub fn new(config: &GlobalConfig) -> io::Result<Replay> {
+        let mut replay_config = (*config.config).clone();
+        replay_config.game.seed = Some(config.computed_config.seed);
+
+        let level = match &config.config.board.level {
+            Some(path) => {
+                let path = Path::new(path);
+                replay_config.board.level = path.file_name().map(|name| name.to_string_lossy().into_owned());
+
+                Some(fs::read_to_string(path)?)
+            }
+            None => None,
+        };
+
+        Ok(Replay {
+            seed: config.computed_config.seed,
+            config: replay_config,
+            level,
+            inputs: Vec::new(),
+            restarts: Vec::new(),
+        })
+    }
+
+    pub fn load(path: &Path) -> io::Result<Replay> {
+        let contents = fs::read_to_string(path)?;
+        let mut replay: Replay = toml::from_str(&contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        replay.config.game.seed = Some(replay.seed);
+        replay.global_config()?;
+
+        Ok(replay)
+    }
+
+    // Replays without a level map go back to the level path
+    pub fn global_config(&self) -> io::Result<GlobalConfig> {
+        let invalid = |e: ConfigError| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+
+        let level = match &self.level {
+            Some(map) => Level::parse(map)?,
+            None => self.config.level().map_err(invalid)?,
+        };
+
+        self.config.validate_with(&level).map_err(invalid)?;
+
+        Ok(GlobalConfig::with_level(self.config.clone(), level))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let contents = toml::to_string(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        fs::write(path, contents)
+    }
+
+    pub fn record(&mut self, step: u64, player: usize, direction: Direction) {
+        self.inputs.push(ReplayInput { step, player, direction });
+    }
+
+    pub fn record_restart(&mut self, step: u64) {
+        self.restarts.push(step);
+    }
+}
+
+pub struct ReplayPlayer {
+    replay: Replay,
+    cursor: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> ReplayPlayer {
+        ReplayPlayer {
+            replay,
+            cursor: 0,
+        }
+    }
+
+    pub fn inputs(&mut self, step: u64, players: usize) -> Vec<Option<Direction>> {
+        let mut inputs = vec![None; players];
+
+        while let Some(input) = self.replay.inputs.get(self.cursor) {
+            if input.step != step {
+                break;
+            }
+
+            if let Some(slot) = inputs.get_mut(input.player) {
+                *slot = Some(input.direction);
+            }
+
+            self.cursor += 1;
+        }
+
+        inputs
+    }
+
+    // How many new rounds were started by hand after that step
+    pub fn restarts_at(&self, step: u64) -> usize {
+        self.replay.restarts.iter().filter(|restart| **restart == step).count()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.cursor >= self.replay.inputs.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::snapshot::BoardSnapshot;
+    use std::sync::Arc;
+
+    const LEVEL: &str = "length = 3\n\n############\n#..........#\n#..>.......#\n#....##....#\n#..........#\n#..........#\n#....A.....#\n#..........#\n#...B......#\n#......<...#\n#..........#\n############\n";
+
+    // A new round after each death, as the game does on playback
+    fn play(simulation: &mut Simulation, mut inputs: impl FnMut(u64) -> Vec<Option<Direction>>) -> Vec<String> {
+        (0..2000)
+            .map(|_| {
+                let outcome = simulation.step(&inputs(simulation.steps));
+                if outcome.died || outcome.cleared {
+                    simulation.restart();
+                }
+
+                serde_json::to_string(&BoardSnapshot::new(simulation)).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_saved_replay_plays_back_the_same_run() {
+        let directory = std::env::temp_dir().join(format!("snake-replay-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let level_path = directory.join("level.txt");
+        fs::write(&level_path, LEVEL).unwrap();
+
+        let mut config = Config::new();
+        config.game.players = Some(2);
+        config.game.seed = Some(3);
+        config.board.level = Some(level_path.to_string_lossy().into_owned());
+
+        let config = GlobalConfig::from_config(config).unwrap();
+        let mut replay = Replay::new(&config).unwrap();
+        let mut simulation = Simulation::new(Arc::new(config)).unwrap();
+        let turns = [Direction::Up, Direction::Left, Direction::Down, Direction::Right];
+
+        // Turns at fixed steps for both players
+        let recorded = play(
+            &mut simulation,
+            |step| {
+                let inputs = vec![
+                    Some(turns[(step / 7 % 4) as usize]).filter(|_| step % 7 == 0),
+                    Some(turns[(step / 11 % 4) as usize]).filter(|_| step % 11 == 0),
+                ];
+
+                for (player, input) in inputs.iter().enumerate() {
+                    if let Some(direction) = input {
+                        replay.record(step, player, *direction);
+                    }
+                }
+
+                inputs
+            },
+        );
+
+        let replay_path = directory.join("replay.toml");
+        replay.save(&replay_path).unwrap();
+
+        // The level file is gone, the replay keeps its own copy of the map
+        fs::remove_file(&level_path).unwrap();
+        let replay = Replay::load(&replay_path).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(replay.config.board.level.as_deref(), Some("level.txt"));
+
+        let mut simulation = Simulation::new(Arc::new(replay.global_config().unwrap())).unwrap();
+        let mut player = ReplayPlayer::new(replay);
+
+        let played = play(&mut simulation, |step| player.inputs(step, 2));
+
+        assert!(recorded == played);
+    }
+}
@@ -1,5 +1,7 @@
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap};
 
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Score {
     pub title: String,
     pub scores: HashMap<String, ScoreElement>,
@@ -19,8 +21,8 @@ impl Score {
         }
     }
 
-    pub fn update_score(&mut self) {
-        self.scores.get_mut("current").unwrap().count += 1;
+    pub fn add(&mut self, points: u32) {
+        self.scores.get_mut("current").unwrap().count += points;
         if self.scores.get("current").unwrap().count > self.scores.get("best").unwrap().count {
             self.scores.get_mut("best").unwrap().count = self.scores.get("current").unwrap().count;
         }
@@ -28,11 +30,21 @@ impl Score {
 
     pub fn reset(&mut self) {
         self.scores.get_mut("death").unwrap().count += 1;
+        self.new_game();
+    }
+
+    pub fn new_game(&mut self) {
         self.scores.get_mut("current").unwrap().count = 0;
     }
 }
 
-#[derive(Clone)]
+impl Default for Score {
+    fn default() -> Score {
+        Score::new()
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct ScoreElement {
     pub title: String,
     pub count: u32,
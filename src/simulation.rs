@@ -0,0 +1,878 @@
+use crate::board::Board;
+use crate::config::{ConfigError, GlobalConfig};
+use crate::enemy::{Contact, Enemy};
+use crate::food::{Food, FoodKind, SPEED_BOOST, SPEED_DURATION};
+use crate::game_mode::{GameMode, Mode, Wall};
+use crate::portal::{Portal, PortalBehavior, PortalExit};
+use crate::power_up::{PowerUp, PowerUpKind, MAGNET_INTERVAL, SCORE_MULTIPLIER, SLOW_FACTOR};
+use crate::score::Score;
+use crate::snake::{DeathCause, Direction, Point};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+
+#[derive(Copy, Clone, Default)]
+pub struct StepOutcome {
+    pub ate_food: bool,
+    pub took_gate: bool,
+    pub took_power_up: bool,
+    pub died: bool,
+    pub cleared: bool,
+}
+
+impl StepOutcome {
+    pub fn merge(&mut self, other: StepOutcome) {
+        self.ate_food |= other.ate_food;
+        self.took_gate |= other.took_gate;
+        self.took_power_up |= other.took_power_up;
+        self.died |= other.died;
+        self.cleared |= other.cleared;
+    }
+}
+
+pub struct Simulation {
+    pub board: Board,
+    pub scores: Vec<Score>,
+    pub steps: u64,
+    pub cleared: bool,
+    pub dead: Vec<(usize, DeathCause)>,
+    pub boost_time: f64,
+    pub level: u32,
+    eaten: u32,
+    progress: f64,
+    power_up_timer: f64,
+    rng: ChaCha8Rng,
+}
+
+impl Simulation {
+    // Every entry point goes through here, a config nobody validated is checked against its level
+    pub fn new(config: Arc<GlobalConfig>) -> Result<Simulation, ConfigError> {
+        config.config.validate_with(&config.computed_config.level)?;
+
+        let game_mode = Arc::new(GameMode::new(config.clone()));
+        let mut rng = ChaCha8Rng::seed_from_u64(config.computed_config.seed);
+
+        let players = config.computed_config.players;
+
+        Ok(Simulation {
+            board: Simulation::new_board(config.clone(), game_mode, &mut rng)?,
+            scores: (0..players)
+                .map(|player| {
+                    let mut score = Score::new();
+                    if players > 1 {
+                        score.title = format!("Player {}", player + 1);
+                    }
+
+                    score
+                })
+                .collect(),
+            steps: 0,
+            cleared: false,
+            dead: Vec::new(),
+            boost_time: 0.0,
+            level: config.config.game.level,
+            eaten: 0,
+            progress: 0.0,
+            power_up_timer: config.config.power_ups.interval,
+            rng,
+        })
+    }
+
+    pub fn restart(&mut self) {
+        for (player, score) in self.scores.iter_mut().enumerate() {
+            if self.dead.iter().any(|(dead, _)| *dead == player) {
+                score.reset();
+            } else {
+                score.new_game();
+            }
+        }
+
+        self.cleared = false;
+        self.dead.clear();
+        self.boost_time = 0.0;
+        self.level = self.board.config.config.game.level;
+        self.eaten = 0;
+        self.progress = 0.0;
+        self.power_up_timer = self.board.config.config.power_ups.interval;
+
+        // The food finds a cell whatever the rng draws, the first board of this config proved there is one
+        self.board = Simulation::new_board(
+            self.board.config.clone(),
+            self.board.game_mode.clone(),
+            &mut self.rng,
+        ).expect("the config already built a board");
+    }
+
+    // Inputs are indexed by player, missing entries keep the current direction
+    pub fn step(&mut self, inputs: &[Option<Direction>]) -> StepOutcome {
+        if self.cleared {
+            return StepOutcome {
+                cleared: true,
+                ..StepOutcome::default()
+            };
+        }
+
+        self.steps += 1;
+
+        for (snake, input) in self.board.snakes.iter_mut().zip(inputs) {
+            if let Some(direction) = input {
+                snake.request_direction(*direction);
+            }
+        }
+
+        let frame = 1.0 / self.board.config.computed_config.fps;
+
+        // Timed food moves elsewhere once its lifetime runs out
+        if let Some(lifetime) = self.board.food.lifetime.as_mut() {
+            *lifetime -= frame;
+
+            if *lifetime <= 0.0 {
+                match self.get_next_food() {
+                    Some(food) => self.board.food = food,
+                    None => self.board.food.lifetime = None,
+                }
+            }
+        }
+
+        self.update_power_ups(frame);
+
+        // Slow motion holds the enemies back as well
+        let slow = self.board.snakes.iter().any(|snake| snake.effects.has(PowerUpKind::Slow));
+        self.update_enemies(if slow { frame * SLOW_FACTOR } else { frame });
+
+        self.progress += self.rate();
+
+        if self.boost_time > 0.0 {
+            self.boost_time -= frame;
+        }
+
+        let mut outcome = StepOutcome::default();
+
+        while self.progress >= 1.0 && !outcome.died && !outcome.cleared {
+            self.progress -= 1.0;
+            outcome.merge(self.advance());
+        }
+
+        outcome
+    }
+
+    // The level, speed food and slow motion change how many moves fit in a step.
+    // Every move keeps the distance of the starting level so the body and the turns stay aligned
+    pub fn rate(&self) -> f64 {
+        let mut rate = self.level as f64 / self.board.config.config.game.level as f64;
+
+        if self.boost_time > 0.0 {
+            rate *= 1.0 + SPEED_BOOST;
+        }
+
+        if self.board.snakes.iter().any(|snake| snake.effects.has(PowerUpKind::Slow)) {
+            rate *= SLOW_FACTOR;
+        }
+
+        rate
+    }
+
+    // How far the snakes move in a step, in cells
+    pub fn step_progress(&self) -> f64 {
+        self.rate() * self.board.snakes[0].frame_handler.get_move_distance()
+    }
+
+    fn update_power_ups(&mut self, frame: f64) {
+        for snake in self.board.snakes.iter_mut() {
+            snake.effects.tick(frame);
+        }
+
+        let config = self.board.config.clone();
+
+        match self.board.power_up.as_mut() {
+            Some(power_up) => {
+                power_up.lifetime -= frame;
+
+                if power_up.lifetime <= 0.0 {
+                    self.board.power_up = None;
+                }
+            }
+            None => {
+                self.power_up_timer -= frame;
+
+                if self.power_up_timer <= 0.0 {
+                    self.power_up_timer = config.config.power_ups.interval;
+                    self.board.power_up = self.get_next_power_up();
+                }
+            }
+        }
+
+        let pull = ((MAGNET_INTERVAL * config.computed_config.fps) as u64).max(1);
+
+        if self.steps.is_multiple_of(pull) {
+            let magnet = self.board.snakes.iter().find(|snake| snake.effects.has(PowerUpKind::Magnet));
+
+            if let Some(snake) = magnet {
+                if let Some(target) = self.magnet_target(snake.head()) {
+                    self.board.food.cell = target;
+                }
+            }
+        }
+    }
+
+    // Enemies step around snakes, walls, food, power-ups, gates and each other, chasers may step onto a head
+    fn update_enemies(&mut self, delta_time: f64) {
+        if self.board.enemies.is_empty() {
+            return;
+        }
+
+        let board = &self.board;
+        let cell = |point: Point| (point.x, point.y);
+
+        let heads: Vec<(i32, i32)> = board.snakes
+            .iter()
+            .map(|snake| (snake.head().x, snake.head().y))
+            .collect();
+
+        let gates: Vec<(i32, i32)> = board.portal
+            .iter()
+            .flat_map(|portal| portal.gates.iter())
+            .map(|gate| cell(gate.cell))
+            .collect();
+
+        let mut blocked: HashSet<(i32, i32)> = board.bodies()
+            .iter()
+            .chain(board.config.computed_config.level.walls.iter())
+            .copied()
+            .chain(Some(board.food.cell))
+            .chain(board.power_up.map(|power_up| power_up.cell))
+            .chain(board.enemies.iter().map(|enemy| enemy.cell))
+            .map(cell)
+            .chain(gates)
+            .collect();
+
+        let size = board.config.config.board.board_block_length as i32;
+
+        for enemy in self.board.enemies.iter_mut() {
+            blocked.remove(&cell(enemy.cell));
+            enemy.update(delta_time, size, &blocked, &heads);
+            blocked.insert(cell(enemy.cell));
+        }
+    }
+
+    // The free cell next to the food on the way to the head, the food stays when it is blocked.
+    // The power-up is not on the grid, it blocks the food as well
+    fn magnet_target(&self, head: Point) -> Option<Point> {
+        let food = self.board.food.cell;
+        let (dx, dy) = (head.x - food.x, head.y - food.y);
+
+        if dx == 0 && dy == 0 {
+            return None;
+        }
+
+        let target = if dx.abs() >= dy.abs() {
+            Point { x: food.x + dx.signum(), y: food.y }
+        } else {
+            Point { x: food.x, y: food.y + dy.signum() }
+        };
+
+        let gates = self.board.portal.iter().flat_map(|portal| portal.gates.iter()).any(|gate| gate.cell == target);
+
+        let free = self.board.grid.is_free(target)
+            && !gates
+            && self.board.power_up.is_none_or(|power_up| power_up.cell != target)
+            && !self.board.enemies.iter().any(|enemy| enemy.cell == target)
+            && !self.board.snakes.iter().any(|snake| snake.overlap_cell(target));
+
+        if free {
+            Some(target)
+        } else {
+            None
+        }
+    }
+
+    fn advance(&mut self) -> StepOutcome {
+        let mut outcome = StepOutcome::default();
+
+        let size = self.board.config.config.board.board_block_length as i32;
+
+        for player in 0..self.board.snakes.len() {
+            let snake = &mut self.board.snakes[player];
+
+            // Only a head taking a new cell eats, picks up or goes through a gate
+            snake.next_head = snake.get_next_point(size);
+            if snake.next_head.is_none() {
+                continue;
+            }
+
+            if let Some(power_up) = self.board.power_up.filter(|power_up| snake.next_move_eat_at(power_up.cell)) {
+                snake.effects.add(power_up.kind);
+                self.board.power_up = None;
+                outcome.took_power_up = true;
+            }
+
+            if self.board.snakes[player].next_move_eat(&self.board.food) {
+                let kind = self.board.food.kind;
+
+                self.speed_up();
+
+                let multiplier = if self.board.snakes[player].effects.has(PowerUpKind::Multiplier) {
+                    SCORE_MULTIPLIER
+                } else {
+                    1
+                };
+
+                self.scores[player].add(kind.points() * multiplier);
+                outcome.ate_food = true;
+
+                match kind {
+                    FoodKind::Poison => {
+                        if let Some(point) = self.board.snakes[player].poison() {
+                            self.board.grid.vacate(point);
+                        }
+                    }
+                    _ => self.board.snakes[player].just_eat = kind.grows(),
+                }
+
+                if kind == FoodKind::Speed {
+                    self.boost_time = SPEED_DURATION;
+                }
+
+                // No free cell left for the next food: the board is cleared once the head took the last one
+                match self.get_next_food() {
+                    Some(food) => self.board.food = food,
+                    None => {
+                        self.cleared = true;
+                        outcome.cleared = true;
+                    }
+                }
+            }
+
+            if self.board.game_mode.mode == Mode::Portal {
+                if self.board.snakes[player].jump.is_some() && !self.board.snakes[player].in_gate() {
+                    let pair = self.board.snakes[player].jump.unwrap().pair;
+
+                    self.board.snakes[player].jump = None;
+                    Portal::release(&mut self.board, pair, &mut self.rng);
+                }
+
+                let portals = &self.board.config.config.portals;
+
+                if let Some(portal) = &mut self.board.portal {
+                    let snake = &mut self.board.snakes[player];
+                    let entry = portal
+                        .entry(snake.next_head.unwrap(), portals.behavior == PortalBehavior::OneWay)
+                        .filter(|_| snake.jump.is_none());
+
+                    if let Some(entry) = entry {
+                        let exit = portal.take(entry);
+
+                        snake.teleport(exit.cell, entry / 2, portals.exit == PortalExit::Rotate);
+                        outcome.took_gate = true;
+                    }
+                }
+            }
+        }
+
+        let solid = self.board.game_mode.wall == Wall::Solid;
+
+        for snake in self.board.snakes.iter_mut() {
+            let head = snake.head();
+            let teleport = snake.jump.is_some_and(|jump| Some(jump.exit) == snake.next_head);
+
+            if snake.update() {
+                self.board.grid.occupy(snake.head());
+
+                if let Some(trail) = snake.trail {
+                    self.board.grid.vacate(trail);
+                }
+            }
+
+            // The shield is spent once it wrapped the snake around a solid wall, a fluid wall wraps without it
+            let next = snake.head();
+            if solid && !teleport && (next.x - head.x).abs() + (next.y - head.y).abs() > 1 {
+                snake.effects.remove(PowerUpKind::Shield);
+            }
+        }
+
+        // Running into an enemy takes a cell off the snake and sends the enemy elsewhere, unless it kills
+        if self.board.config.config.enemies.contact == Contact::Shrink {
+            for player in 0..self.board.snakes.len() {
+                let head = self.board.snakes[player].head();
+                let enemy = self.board.enemies.iter().position(|enemy| enemy.cell == head);
+
+                if let Some(enemy) = enemy {
+                    self.shrink(player);
+
+                    match Enemy::free_cell(&self.board, &self.board.enemies, &mut self.rng) {
+                        Some(cell) => self.board.enemies[enemy].cell = cell,
+                        None => {
+                            self.board.enemies.remove(enemy);
+                        }
+                    }
+                }
+            }
+        }
+
+        // A head running into any part of another snake kills it,
+        // two heads meeting on the same cell kill both players
+        let snakes = &self.board.snakes;
+        let enemies = &self.board.enemies;
+        self.dead = (0..snakes.len())
+            .filter_map(|player| {
+                let snake = &snakes[player];
+                let head = snake.head();
+
+                let cause = snake.death_cause(size, &self.board.config.computed_config.level).or_else(|| snakes
+                    .iter()
+                    .enumerate()
+                    .position(|(other, other_snake)| other != player && other_snake.overlap_cell(head))
+                    .map(DeathCause::Snake))
+                .or_else(|| enemies
+                    .iter()
+                    .find(|enemy| enemy.cell == head)
+                    .map(|enemy| DeathCause::Enemy(enemy.kind)));
+
+                cause.map(|cause| (player, cause))
+            })
+            .collect();
+
+        outcome.died = !self.dead.is_empty();
+
+        outcome
+    }
+
+    fn shrink(&mut self, player: usize) {
+        if let Some(point) = self.board.snakes[player].shrink() {
+            self.board.grid.vacate(point);
+        }
+    }
+
+    // Food lands on cells free of snakes, gates, enemies and the power-up
+    pub fn get_next_food(&mut self) -> Option<Food> {
+        let board = &self.board;
+        let rng = &mut self.rng;
+
+        let cell = board.grid.get_random_position(rng, |cell| !board.is_taken(*cell))?;
+        let kind = FoodKind::random(&board.config.config.food, rng);
+
+        Some(Food::new(cell, kind))
+    }
+
+    // One level more every few food eaten by any player, up to the cap
+    fn speed_up(&mut self) {
+        self.eaten += 1;
+
+        let config = &self.board.config.config;
+
+        if let Some(every) = config.game.speed_up_every {
+            self.level = (config.game.level + self.eaten / every).min(config.level_cap().max(config.game.level));
+        }
+    }
+
+    // Power-ups only land on cells free of snakes, food, gates and enemies
+    pub fn get_next_power_up(&mut self) -> Option<PowerUp> {
+        let board = &self.board;
+        let rng = &mut self.rng;
+
+        let kind = PowerUpKind::random(&board.config.config.power_ups, rng)?;
+        let cell = board.grid.get_random_position(rng, |cell| !board.is_taken(*cell))?;
+
+        Some(PowerUp::new(cell, kind))
+    }
+
+    fn new_board(config: Arc<GlobalConfig>, game_mode: Arc<GameMode>, rng: &mut ChaCha8Rng) -> Result<Board, ConfigError> {
+        let mut board = Board::new(config, game_mode, None, rng)?;
+
+        if board.game_mode.mode == Mode::Portal {
+            board.portal = Portal::new(&board, rng);
+        }
+
+        board.enemies = Enemy::spawn(&board, rng);
+
+        Ok(board)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::enemy::EnemyKind;
+    use crate::level::Level;
+    use crate::portal::Gate;
+    use crate::snapshot::BoardSnapshot;
+
+    // Every feature drawing from the rng, with turns at fixed steps
+    fn run(seed: u64) -> Vec<String> {
+        let mut config = Config::new();
+        config.game.mode = Mode::Portal;
+        config.game.wall = Wall::Fluid;
+        config.game.seed = Some(seed);
+        config.food.golden = 1;
+        config.food.poison = 1;
+        config.power_ups.ghost = 1;
+        config.power_ups.magnet = 1;
+        config.portals.pairs = 2;
+        config.enemies.patrol = 1;
+        config.enemies.ball = 1;
+
+        let mut simulation = Simulation::new(Arc::new(GlobalConfig::from_config(config).unwrap())).unwrap();
+        let turns = [Direction::Up, Direction::Left, Direction::Down, Direction::Right];
+
+        (0..3000)
+            .map(|step| {
+                let input = match step % 45 {
+                    0 => Some(turns[step / 45 % turns.len()]),
+                    _ => None,
+                };
+
+                let outcome = simulation.step(&[input]);
+                if outcome.died || outcome.cleared {
+                    simulation.restart();
+                }
+
+                serde_json::to_string(&BoardSnapshot::new(&simulation)).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_same_run() {
+        assert!(run(42) == run(42));
+        assert!(run(42) != run(43));
+    }
+
+    #[test]
+    fn seed_out_of_toml_range_is_rejected() {
+        let mut config = Config::new();
+
+        config.game.seed = Some(i64::MAX as u64);
+        assert!(config.validate().is_ok());
+
+        config.game.seed = Some(i64::MAX as u64 + 1);
+        assert!(config.validate().is_err());
+    }
+
+    // Runs the shielded snake straight into the right edge and reports whether the shield is left
+    fn shield_after_wrap(wall: Wall) -> bool {
+        let mut config = config();
+        config.game.wall = wall;
+
+        let mut simulation = simulation_with(config);
+        simulation.board.snakes[0].effects.add(PowerUpKind::Shield);
+
+        loop {
+            let head = simulation.board.snakes[0].head();
+            let outcome = simulation.step(&[None]);
+            assert!(!outcome.died);
+
+            if simulation.board.snakes[0].head().x < head.x {
+                return simulation.board.snakes[0].effects.has(PowerUpKind::Shield);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_config_is_rejected() {
+        let mut config = Config::new();
+        config.game.level = 0;
+
+        assert!(Simulation::new(Arc::new(GlobalConfig::with_level(config, Level::empty(20)))).is_err());
+    }
+
+    #[test]
+    fn shield_is_spent_on_a_solid_wall_only() {
+        assert!(!shield_after_wrap(Wall::Solid));
+        assert!(shield_after_wrap(Wall::Fluid));
+    }
+
+    // A fixed seed and no power-up showing up on its own
+    fn config() -> Config {
+        let mut config = Config::new();
+        config.game.seed = Some(1);
+        config.power_ups.interval = 1000.0;
+
+        config
+    }
+
+    fn simulation_with(config: Config) -> Simulation {
+        Simulation::new(Arc::new(GlobalConfig::from_config(config).unwrap())).unwrap()
+    }
+
+    pub(crate) fn simulation() -> Simulation {
+        simulation_with(config())
+    }
+
+    // Steps until the head takes its next cell, or the game ends on the way
+    pub(crate) fn next_cell(simulation: &mut Simulation) -> StepOutcome {
+        let head = simulation.board.snakes[0].head();
+
+        loop {
+            let outcome = simulation.step(&[None]);
+
+            if outcome.died || outcome.cleared || simulation.board.snakes[0].head() != head {
+                return outcome;
+            }
+        }
+    }
+
+    #[test]
+    fn food_on_the_next_cell_is_eaten() {
+        let mut simulation = simulation();
+        let snake = &simulation.board.snakes[0];
+        let (ahead, length) = (snake.head().moved(snake.direction()), snake.body.len());
+
+        simulation.board.food = Food::new(ahead, FoodKind::Normal);
+
+        assert!(next_cell(&mut simulation).ate_food);
+        assert_eq!(simulation.board.snakes[0].body.len(), length + 1);
+        assert_ne!(simulation.board.food.cell, ahead);
+    }
+
+    #[test]
+    fn eating_raises_the_level_up_to_the_cap() {
+        let mut config = config();
+        config.game.speed_up_every = Some(1);
+        config.game.level_cap = Some(6);
+
+        let mut simulation = simulation_with(config);
+        assert_eq!((simulation.level, simulation.rate()), (4, 1.0));
+
+        for (level, rate) in [(5, 1.25), (6, 1.5), (6, 1.5)] {
+            let snake = &simulation.board.snakes[0];
+            simulation.board.food = Food::new(snake.head().moved(snake.direction()), FoodKind::Normal);
+
+            assert!(next_cell(&mut simulation).ate_food);
+            assert_eq!((simulation.level, simulation.rate()), (level, rate));
+        }
+    }
+
+    fn food_ahead(simulation: &mut Simulation, kind: FoodKind) {
+        let snake = &simulation.board.snakes[0];
+        simulation.board.food = Food::new(snake.head().moved(snake.direction()), kind);
+    }
+
+    #[test]
+    fn poison_shrinks_the_snake_down_to_death() {
+        let mut simulation = simulation();
+        let start = simulation.board.snakes[0].body.len();
+        assert!(start > 1);
+
+        for length in (1..start).rev() {
+            food_ahead(&mut simulation, FoodKind::Poison);
+
+            assert!(next_cell(&mut simulation).ate_food);
+            assert_eq!(simulation.board.snakes[0].body.len(), length);
+            assert_eq!(simulation.scores[0].scores["current"].count, 0);
+        }
+
+        food_ahead(&mut simulation, FoodKind::Poison);
+
+        assert!(next_cell(&mut simulation).died);
+        assert!(matches!(simulation.dead[..], [(0, DeathCause::Poison)]));
+    }
+
+    #[test]
+    fn golden_food_moves_once_its_lifetime_runs_out() {
+        let mut simulation = simulation();
+        let frame = 1.0 / simulation.board.config.computed_config.fps;
+        let cell = Point { x: 15, y: 15 };
+
+        simulation.board.food = Food::new(cell, FoodKind::Golden);
+        assert_eq!(simulation.board.food.lifetime, Some(5.0));
+        simulation.board.food.lifetime = Some(9.5 * frame);
+
+        for _ in 0..9 {
+            simulation.step(&[None]);
+        }
+        assert_eq!(simulation.board.food.cell, cell);
+
+        simulation.step(&[None]);
+        assert_ne!(simulation.board.food.cell, cell);
+        assert!(simulation.board.food.kind == FoodKind::Normal && simulation.board.food.lifetime.is_none());
+    }
+
+    #[test]
+    fn speed_food_boosts_the_rate_for_a_while() {
+        let mut simulation = simulation();
+        let frame = 1.0 / simulation.board.config.computed_config.fps;
+
+        food_ahead(&mut simulation, FoodKind::Speed);
+
+        assert!(next_cell(&mut simulation).ate_food);
+        assert_eq!(simulation.scores[0].scores["current"].count, 1);
+        assert_eq!(simulation.rate(), 1.0 + SPEED_BOOST);
+        assert!(simulation.boost_time > SPEED_DURATION - 1.0);
+
+        simulation.boost_time = 0.5 * frame;
+        simulation.step(&[None]);
+        assert_eq!(simulation.rate(), 1.0);
+    }
+
+    #[test]
+    fn magnet_keeps_the_food_off_the_power_up() {
+        let mut simulation = simulation();
+        let head = simulation.board.snakes[0].head();
+        let food = Point { x: head.x, y: head.y + 4 };
+        let between = Point { x: head.x, y: head.y + 3 };
+
+        simulation.board.food = Food::new(food, FoodKind::Normal);
+        assert_eq!(simulation.magnet_target(head), Some(between));
+
+        simulation.board.power_up = Some(PowerUp::new(between, PowerUpKind::Ghost));
+        assert_eq!(simulation.magnet_target(head), None);
+    }
+
+    #[test]
+    fn enemy_on_the_next_cell_kills() {
+        let mut simulation = simulation();
+        let snake = &simulation.board.snakes[0];
+        let ahead = snake.head().moved(snake.direction());
+
+        simulation.board.enemies.push(Enemy::new(ahead, EnemyKind::Patrol, (0, 0)));
+
+        assert!(next_cell(&mut simulation).died);
+        assert!(matches!(simulation.dead[..], [(0, DeathCause::Enemy(EnemyKind::Patrol))]));
+    }
+
+    // The snake starts at (4, 4) heading right, with these gates in place of the random ones
+    fn portal_simulation(behavior: PortalBehavior, exit: PortalExit, gates: &[Point]) -> Simulation {
+        let mut config = config();
+        config.game.mode = Mode::Portal;
+        config.portals.behavior = behavior;
+        config.portals.exit = exit;
+
+        let mut simulation = simulation_with(config);
+        simulation.board.food = Food::new(Point { x: 1, y: 18 }, FoodKind::Normal);
+        simulation.board.portal = Some(Portal {
+            gates: gates.iter().map(|cell| Gate { cell: *cell, used: false }).collect(),
+        });
+
+        simulation
+    }
+
+    fn gates(simulation: &Simulation) -> Vec<(Point, bool)> {
+        simulation.board.portal.as_ref().unwrap().gates.iter().map(|gate| (gate.cell, gate.used)).collect()
+    }
+
+    // Moves on until the tail is out of the exit gate
+    fn through_gate(simulation: &mut Simulation) {
+        while simulation.board.snakes[0].jump.is_some() {
+            assert!(!next_cell(simulation).died);
+        }
+    }
+
+    const AHEAD: Point = Point { x: 5, y: 4 };
+    const EXIT: Point = Point { x: 10, y: 12 };
+
+    #[test]
+    fn each_gate_leads_to_its_own_pair() {
+        let far = [Point { x: 15, y: 2 }, Point { x: 2, y: 15 }];
+        let mut simulation = portal_simulation(PortalBehavior::Persistent, PortalExit::Keep, &[far[0], far[1], AHEAD, EXIT]);
+
+        assert!(next_cell(&mut simulation).took_gate);
+        assert_eq!(simulation.board.snakes[0].head(), EXIT);
+
+        let portal = simulation.board.portal.as_ref().unwrap();
+        assert!(!portal.is_used(0) && portal.is_used(1));
+        assert_eq!(portal.entry(far[1], false), Some(1));
+        assert_eq!(portal.entry(EXIT, false), None);
+    }
+
+    #[test]
+    fn one_way_gate_is_not_entered_from_its_exit() {
+        let mut simulation = portal_simulation(PortalBehavior::OneWay, PortalExit::Keep, &[EXIT, AHEAD]);
+
+        assert!(!next_cell(&mut simulation).took_gate);
+        assert_eq!(simulation.board.snakes[0].head(), AHEAD);
+        assert_eq!(gates(&simulation), vec![(EXIT, false), (AHEAD, false)]);
+
+        // The other way round it is a plain gate
+        let mut simulation = portal_simulation(PortalBehavior::OneWay, PortalExit::Keep, &[AHEAD, EXIT]);
+
+        assert!(next_cell(&mut simulation).took_gate);
+        assert_eq!(simulation.board.snakes[0].head(), EXIT);
+    }
+
+    #[test]
+    fn persistent_pair_opens_again_in_place() {
+        let mut simulation = portal_simulation(PortalBehavior::Persistent, PortalExit::Keep, &[AHEAD, EXIT]);
+
+        next_cell(&mut simulation);
+        assert_eq!(gates(&simulation), vec![(AHEAD, true), (EXIT, false)]);
+
+        through_gate(&mut simulation);
+        assert_eq!(gates(&simulation), vec![(AHEAD, false), (EXIT, false)]);
+    }
+
+    #[test]
+    fn respawn_pair_moves_once_used() {
+        let mut simulation = portal_simulation(PortalBehavior::Respawn, PortalExit::Keep, &[AHEAD, EXIT]);
+
+        next_cell(&mut simulation);
+        assert_eq!(gates(&simulation), vec![(AHEAD, true), (EXIT, false)]);
+
+        through_gate(&mut simulation);
+        let moved = gates(&simulation);
+        assert!(moved.iter().all(|(cell, used)| !used && *cell != AHEAD && *cell != EXIT));
+        assert!(moved.iter().all(|(cell, _)| !simulation.board.snakes[0].body.contains(cell) && *cell != simulation.board.food.cell));
+    }
+
+    #[test]
+    fn exit_keeps_or_rotates_the_heading() {
+        let mut simulation = portal_simulation(PortalBehavior::Persistent, PortalExit::Keep, &[AHEAD, EXIT]);
+
+        next_cell(&mut simulation);
+        next_cell(&mut simulation);
+        assert!(simulation.board.snakes[0].direction() == Direction::Right);
+        assert_eq!(simulation.board.snakes[0].head(), Point { x: 11, y: 12 });
+
+        // A clockwise quarter turn from right heads down
+        let mut simulation = portal_simulation(PortalBehavior::Persistent, PortalExit::Rotate, &[AHEAD, EXIT]);
+
+        next_cell(&mut simulation);
+        next_cell(&mut simulation);
+        assert!(simulation.board.snakes[0].direction() == Direction::Down);
+        assert_eq!(simulation.board.snakes[0].head(), Point { x: 10, y: 13 });
+    }
+
+    // The map sets the board size and the spawns as a level file does
+    fn level_simulation(map: &str, players: u32) -> Simulation {
+        let mut config = config();
+        config.game.players = Some(players);
+        config.board.level = Some(String::from("level.txt"));
+
+        Simulation::new(Arc::new(GlobalConfig::with_level(config, Level::parse(map).unwrap()))).unwrap()
+    }
+
+    #[test]
+    fn heads_meeting_kill_both_players() {
+        let mut simulation = level_simulation("#######\n#.....#\n#.>.<.#\n#.....#\n#.....#\n#.....#\n#######\n", 2);
+
+        assert!(next_cell(&mut simulation).died);
+        assert_eq!(simulation.board.snakes[0].head(), simulation.board.snakes[1].head());
+        assert!(matches!(simulation.dead[..], [(0, DeathCause::Snake(1)), (1, DeathCause::Snake(0))]));
+    }
+
+    #[test]
+    fn head_into_another_body_kills_that_player_only() {
+        // Player 2 moves on, player 1 runs into the cell right behind its head
+        let mut simulation = level_simulation("#######\n#.....#\n#...v.#\n#...>.#\n#.....#\n#.....#\n#######\n", 2);
+
+        assert!(next_cell(&mut simulation).died);
+        assert_eq!(simulation.board.snakes[0].head(), simulation.board.snakes[1].body[1]);
+        assert!(matches!(simulation.dead[..], [(0, DeathCause::Snake(1))]));
+    }
+
+    #[test]
+    fn eating_the_last_free_cell_clears_the_board() {
+        // The food can only land right in front of the snake
+        let mut simulation = level_simulation("length = 1\n\n####\n#>.#\n####\n####\n", 1);
+        assert_eq!(simulation.board.food.cell, Point { x: 2, y: 1 });
+
+        let outcome = next_cell(&mut simulation);
+        assert!(outcome.ate_food && outcome.cleared);
+        assert_eq!(simulation.board.snakes[0].head(), Point { x: 2, y: 1 });
+        assert!(simulation.cleared);
+        assert!(simulation.step(&[None]).cleared);
+    }
+}
@@ -1,14 +1,19 @@
 use crate::config::GlobalConfig;
+use crate::enemy::EnemyKind;
 use crate::food::Food;
 use crate::game_mode::{GameMode, Wall};
-use crate::portal::{Portal, Gate};
+use crate::level::Level;
+use crate::power_up::{Effects, PowerUpKind};
 
-use std::collections::LinkedList;
-use piston_window::Key;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::sync::Arc;
 
 
-#[derive(PartialEq, Copy, Clone)]
+// Turns pressed in a row wait for the next cells, one is taken at each cell
+pub const TURN_QUEUE: usize = 3;
+
+#[derive(PartialEq, Copy, Clone, Deserialize, Serialize)]
 pub enum Direction {
     Up,
     Down,
@@ -16,6 +21,15 @@ pub enum Direction {
     Right,
 }
 
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum DeathCause {
+    Wall,
+    Itself,
+    Snake(usize),
+    Enemy(EnemyKind),
+    Poison,
+}
+
 impl Direction {
     pub fn opposite(&self) -> Direction {
         match *self {
@@ -25,343 +39,344 @@ impl Direction {
             Direction::Right => Direction::Left,
         }
     }
-}
 
-#[derive(Copy, Clone)]
-pub struct Point {
-    pub x: f64,
-    pub y: f64,
+    pub fn clockwise(&self) -> Direction {
+        match *self {
+            Direction::Up => Direction::Right,
+            Direction::Right => Direction::Down,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Up,
+        }
+    }
+
+    pub fn counter_clockwise(&self) -> Direction {
+        self.clockwise().opposite()
+    }
 }
 
-#[derive(Copy, Clone)]
-pub struct Jump {
-    gate_head: Option<Point>,
-    pub is_jumping: bool,
-    gate_end_position: Option<(f64, f64)>,
+// A whole board cell, taken by snake bodies as by food, power-ups, gates and enemies
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
 }
 
-impl Jump {
-    pub fn new(point: Point) -> Jump {
-        Jump {
-            gate_head: Some(point),
-            is_jumping: true,
-            gate_end_position: Some((point.x, point.y)),
+impl Point {
+    pub(crate) fn moved(&self, direction: Direction) -> Point {
+        match direction {
+            Direction::Up => Point { x: self.x, y: self.y - 1 },
+            Direction::Down => Point { x: self.x, y: self.y + 1 },
+            Direction::Left => Point { x: self.x - 1, y: self.y },
+            Direction::Right => Point { x: self.x + 1, y: self.y },
         }
     }
 }
 
+// The jump lasts until the tail comes out of the exit gate
+#[derive(Copy, Clone)]
+pub struct Jump {
+    pub exit: Point,
+    pub pair: usize,
+}
+
+// The body is one cell per block, head first. The head takes a new cell once the progress
+// of its moves reaches a whole cell, the progress and the trail left by the tail are only drawn
 #[derive(Clone)]
 pub struct Snake {
-    pub body: LinkedList<Point>,
+    pub body: VecDeque<Point>,
     direction: Direction,
-    request_direction: Direction,
-    request_position: Option<(f64, f64)>,
+    turns: VecDeque<Direction>,
+    pub progress: f64,
+    pub trail: Option<Point>,
     pub just_eat: bool,
     pub next_head: Option<Point>,
     pub frame_handler: FrameHandler,
     blocks_to_add: u32,
-    middle_block_passed: bool,
     game_mode: Arc<GameMode>,
     pub jump: Option<Jump>,
+    pub effects: Effects,
+    poisoned: bool,
 }
 
 impl Snake {
-    pub fn new(x: f64, y: f64, frame_handler: FrameHandler, game_mode: Arc<GameMode>) -> Snake {
-        let mut body: LinkedList<Point> = LinkedList::new();
+    pub fn new(head: Point, direction: Direction, length: u32, frame_handler: FrameHandler, game_mode: Arc<GameMode>) -> Snake {
+        let mut body = VecDeque::new();
+        let mut cell = head;
 
-        for f in FloatIterator::new_with_step(0.0, 2.0, frame_handler.get_move_distance()) {
-            body.push_back(Point { x: x - f, y });
+        for _ in 0..length.max(1) {
+            body.push_back(cell);
+            cell = cell.moved(direction.opposite());
         }
 
         Snake {
             body,
-            direction: Direction::Right,
-            request_direction: Direction::Right,
-            request_position: None,
+            direction,
+            turns: VecDeque::new(),
+            progress: 0.0,
+            trail: Some(cell),
             just_eat: false,
             next_head: None,
             frame_handler,
             blocks_to_add: 0,
-            middle_block_passed: false,
             game_mode: game_mode.clone(),
             jump: None,
+            effects: Effects::default(),
+            poisoned: false,
         }
     }
 
-    pub fn head_position(&self) -> (f64, f64) {
-        let head = self.body.front().unwrap();
-        (head.x, head.y)
+    pub fn direction(&self) -> Direction {
+        self.direction
     }
 
-    pub fn get_next_point(&self, board_size: &f64, block_size: &f64) -> Point {
-        let (head_x, head_y) = self.head_position();
-
-        let move_distance = self.frame_handler.get_move_distance();
-        let max_distance = *board_size as f64 / *block_size as f64 - 1.0;
-
-        match self.game_mode.wall {
-            Wall::Solid => match self.direction {
-                Direction::Up => Point { x: head_x, y: head_y - move_distance },
-                Direction::Down => Point { x: head_x, y: head_y + move_distance },
-                Direction::Left => Point { x: head_x - move_distance, y: head_y },
-                Direction::Right => Point { x: head_x + move_distance, y: head_y },
-            },
-            Wall::Fluid => match self.direction {
-                Direction::Up => {
-                    let next_y = if head_y - move_distance < 0.0 {
-                        max_distance
-                    } else {
-                        head_y - move_distance
-                    };
-
-                    Point { x: head_x, y: next_y }
-                }
-                Direction::Down => {
-                    let next_y = if head_y + move_distance > max_distance {
-                        0.0
-                    } else {
-                        head_y + move_distance
-                    };
-
-                    Point { x: head_x, y: next_y }
-                }
-                Direction::Left => {
-                    let next_x = if head_x - move_distance < 0.0 {
-                        max_distance
-                    } else {
-                        head_x - move_distance
-                    };
-
-                    Point { x: next_x, y: head_y }
-                }
-                Direction::Right => {
-                    let next_x = if head_x + move_distance > max_distance {
-                        0.0
-                    } else {
-                        head_x + move_distance
-                    };
-
-                    Point { x: next_x, y: head_y }
-                }
-            }
-        }
+    pub fn head(&self) -> Point {
+        *self.body.front().unwrap()
     }
 
-    pub fn request_direction(&mut self, key: Key) {
-        self.request_direction = match key {
-            Key::Up => Direction::Up,
-            Key::Down => Direction::Down,
-            Key::Left => Direction::Left,
-            Key::Right => Direction::Right,
-            _ => self.request_direction.clone()
-        };
+    pub fn pending_turns(&self) -> usize {
+        self.turns.len()
     }
 
-    pub fn overlap_tail(&self, x: &f64, y: &f64) -> bool {
-        let mut index = 0;
-        for point in &self.body {
-            if index > ((1.0 / self.frame_handler.get_move_distance()) / 2.0).ceil() as u32 {
-                if (point.x, point.y) == (*x, *y) {
-                    return true;
-                }
-            }
-            index += 1;
-        }
-
-        false
+    // Where the snake goes once the turns waiting are taken
+    pub fn last_heading(&self) -> Direction {
+        self.turns.back().copied().unwrap_or(self.direction)
     }
 
-    pub fn update(&mut self, delta_time: f64) {
-        self.frame_handler.current_delta += delta_time;
-
-        if self.frame_handler.current_delta < (1.0 / &self.frame_handler.config.computed_config.fps) {
-            return;
+    // The way the head goes into its next cell, turning back is ignored
+    fn heading(&self) -> Direction {
+        match self.turns.front() {
+            Some(turn) if *turn != self.direction.opposite() => *turn,
+            _ => self.direction,
         }
+    }
 
-        self.frame_handler.current_delta = 0.0;
-
-        match self.jump.as_mut() {
-            Some(jump) => {
-                if let Some(point) = jump.gate_head {
-                    self.body.push_front(point);
-                    jump.gate_head = None;
-                    jump.is_jumping = true;
-                    return;
-                }
-            }
-            None => ()
+    // The cell the head takes on this move, none while it is still crossing its cell
+    pub fn get_next_point(&self, size: i32) -> Option<Point> {
+        if self.progress + self.frame_handler.get_move_distance() < 1.0 {
+            return None;
         }
 
+        let next = self.head().moved(self.heading());
 
-        let mut need_new_head = true;
-
-        if self.direction.opposite() != self.request_direction {
-            if let Some((past_head_x, past_head_y)) = self.request_position {
-                let (head_x, head_y) = self.head_position();
+        // A shield turns the next solid wall hit into a wrap
+        let wall = match self.game_mode.wall {
+            Wall::Solid if self.effects.has(PowerUpKind::Shield) => Wall::Fluid,
+            wall => wall,
+        };
 
-                if head_x.round() != past_head_x.round() || head_y.round() != past_head_y.round() {
-                    self.middle_block_passed = true
-                }
+        match wall {
+            Wall::Solid => Some(next),
+            Wall::Fluid => Some(Point {
+                x: next.x.rem_euclid(size),
+                y: next.y.rem_euclid(size),
+            }),
+        }
+    }
 
-                if self.at_ceil_edge((&head_x, &head_y)) && self.middle_block_passed {
-                    self.middle_block_passed = false;
-                    need_new_head = false;
-                    self.request_position = None;
+    // Queued after the turns already waiting, a turn back or the same way again is dropped
+    pub fn request_direction(&mut self, direction: Direction) {
+        let last = self.last_heading();
 
-                    self.body.push_front(Point { x: head_x.round(), y: head_y.round() });
-                    self.direction = self.request_direction;
-                }
-            }
+        if self.turns.len() < TURN_QUEUE && direction != last && direction != last.opposite() {
+            self.turns.push_back(direction);
         }
+    }
 
-        if self.direction.opposite() != self.request_direction {
-            self.request_position = Some(self.head_position());
-        }
+    // True when the head took a new cell
+    pub fn update(&mut self) -> bool {
+        self.progress += self.frame_handler.get_move_distance();
 
-        if need_new_head {
-            self.body.push_front(self.next_head.unwrap().clone());
-        }
+        let next = match self.next_head.take() {
+            Some(next) => next,
+            None => return false,
+        };
+
+        self.progress -= 1.0;
+        self.direction = self.heading();
+        self.turns.pop_front();
+        self.body.push_front(next);
 
         if self.just_eat {
-            self.blocks_to_add += (1.0 / self.frame_handler.get_move_distance()).ceil() as u32;
+            self.blocks_to_add += 1;
             self.just_eat = false;
         }
 
-        if self.blocks_to_add > 0 {
+        self.trail = if self.blocks_to_add > 0 {
             self.blocks_to_add -= 1;
+            None
+        } else {
+            self.body.pop_back()
+        };
 
-            return;
-        } else if self.blocks_to_add == 0 {
-            self.body.pop_back().unwrap();
-        }
+        true
     }
 
-    pub fn next_move_eat(&self, food: &Food) -> bool {
-        let Point { x, y } = self.next_head.unwrap();
+    // One cell comes off the tail and is returned, the snake keeps at least one cell.
+    // A snake going through a gate keeps its tail, the jump ends when the tail leaves the gate
+    pub fn shrink(&mut self) -> Option<Point> {
+        if self.jump.is_some() {
+            return None;
+        }
+
+        if self.blocks_to_add > 0 {
+            self.blocks_to_add -= 1;
+        } else if self.body.len() > 1 {
+            self.trail = None;
+            return self.body.pop_back();
+        }
 
-        return (x.round(), y.round()) == (food.x, food.y);
+        None
     }
 
-    pub fn next_move_take_gate(&self, portal: &mut Portal) -> bool {
-        let Point { x, y } = self.next_head.unwrap();
+    // Poison takes a cell off like shrink, and kills a snake left with its head only
+    pub fn poison(&mut self) -> Option<Point> {
+        self.poisoned = self.jump.is_none() && self.blocks_to_add == 0 && self.body.len() == 1;
 
-        for gate in portal.gates.iter() {
-            let mut gate = gate.lock().unwrap();
-            if (x.round(), y.round()) != (gate.x, gate.y) {
-                continue;
-            }
+        self.shrink()
+    }
 
-            gate.used = true;
+    pub fn overlap_cell(&self, cell: Point) -> bool {
+        self.body.contains(&cell)
+    }
 
-            return true;
-        }
+    pub fn next_move_eat(&self, food: &Food) -> bool {
+        self.next_move_eat_at(food.cell)
+    }
 
-        false
+    pub fn next_move_eat_at(&self, cell: Point) -> bool {
+        self.next_head == Some(cell)
     }
 
-    pub fn teleport(&mut self, portal: Portal) {
-        let Gate { x, y, .. } = *portal.gates
-            .iter()
-            .filter(|gate| !gate.lock().unwrap().used)
-            .last()
-            .unwrap()
-            .lock()
-            .unwrap();
+    // The head comes out of the exit gate instead of its next cell.
+    // A rotating exit turns it clockwise and drops the turns pressed for the old heading
+    pub fn teleport(&mut self, exit: Point, pair: usize, rotate: bool) {
+        self.next_head = Some(exit);
+        self.jump = Some(Jump { exit, pair });
 
-        self.jump = Some(Jump::new(Point { x, y }));
+        if rotate {
+            self.direction = self.heading().clockwise();
+            self.turns.clear();
+        }
     }
 
-    pub fn is_dead(&self, board_size: &f64, block_size: &f64) -> bool {
-        let (x, y) = self.head_position();
-        let max_distance = *board_size as f64 / *block_size as f64 - 1.0;
+    pub fn death_cause(&self, size: i32, level: &Level) -> Option<DeathCause> {
+        let head = self.head();
 
-        match self.game_mode.wall {
-            Wall::Fluid => self.overlap_tail(&x, &y) && (x != max_distance && y != max_distance),
-            Wall::Solid => self.overlap_tail(&x, &y) || x < 0.0 || x > max_distance || y < 0.0 || y > max_distance
+        if head.x < 0 || head.y < 0 || head.x >= size || head.y >= size {
+            return Some(DeathCause::Wall);
         }
-    }
 
-    pub fn in_gate(&mut self) -> bool {
-        let Point { x: tail_x, y: tail_y } = self.body.back().unwrap();
-        let (gate_x, gate_y) = self.jump.unwrap().gate_end_position.unwrap();
+        if level.is_wall(head) {
+            return Some(DeathCause::Wall);
+        }
 
-        if self.jump.unwrap().is_jumping && (tail_x, tail_y) == (&gate_x, &gate_y) {
-            return false;
+        if self.poisoned {
+            return Some(DeathCause::Poison);
         }
 
-        true
+        // A ghost goes through its own body
+        if !self.effects.has(PowerUpKind::Ghost) && self.body.iter().skip(1).any(|point| *point == head) {
+            return Some(DeathCause::Itself);
+        }
+
+        None
     }
 
-    fn at_ceil_edge(&self, (head_x, head_y): (&f64, &f64)) -> bool {
-        match self.direction {
-            Direction::Up => (head_y - self.frame_handler.get_move_distance()).trunc() != head_y.trunc()
-                || (head_y > &0.0 && head_y <= &self.frame_handler.get_move_distance()),
-            Direction::Down => (head_y + self.frame_handler.get_move_distance()).trunc() != head_y.trunc(),
-            Direction::Left => (head_x - self.frame_handler.get_move_distance()).trunc() != head_x.trunc()
-                || (head_x > &0.0 && head_x <= &self.frame_handler.get_move_distance()),
-            Direction::Right => (head_x + self.frame_handler.get_move_distance()).trunc() != head_x.trunc(),
-        }
+    pub fn in_gate(&self) -> bool {
+        self.body.back() != self.jump.map(|jump| jump.exit).as_ref()
     }
 }
 
 #[derive(Clone)]
 pub struct FrameHandler {
     pub config: Arc<GlobalConfig>,
-    current_delta: f64,
 }
 
 impl FrameHandler {
     pub fn new(config: Arc<GlobalConfig>) -> FrameHandler {
         FrameHandler {
             config,
-            current_delta: 0.0,
         }
     }
 
     pub fn get_move_distance(&self) -> f64 {
-        (self.config.computed_config.block_size / &self.config.computed_config.fps) * &self.config.computed_config.move_delay
+        (self.config.computed_config.block_size / self.config.computed_config.fps) * self.config.computed_config.move_delay
     }
 }
 
-pub struct FloatIterator {
-    current: u64,
-    current_back: u64,
-    steps: u64,
-    start: f64,
-    end: f64,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::tests::{next_cell, simulation};
+
+    fn snake() -> Snake {
+        let config = Arc::new(GlobalConfig::new());
 
-impl FloatIterator {
-    pub fn new(start: f64, end: f64, steps: u64) -> FloatIterator {
-        FloatIterator {
-            current: 0,
-            current_back: steps,
-            steps,
-            start,
-            end,
+        Snake::new(Point { x: 10, y: 10 }, Direction::Right, 3, FrameHandler::new(config.clone()), Arc::new(GameMode::new(config)))
+    }
+
+    #[test]
+    fn turns_queue_up_to_the_limit() {
+        let mut snake = snake();
+
+        for direction in [Direction::Up, Direction::Left, Direction::Down, Direction::Right] {
+            snake.request_direction(direction);
         }
+
+        assert_eq!(snake.pending_turns(), TURN_QUEUE);
+        assert!(snake.last_heading() == Direction::Down);
     }
 
-    pub fn new_with_step(start: f64, end: f64, step: f64) -> FloatIterator {
-        let steps = ((end - start) / step).abs().round() as u64;
-        FloatIterator::new(start, end, steps)
+    #[test]
+    fn turn_back_from_the_last_queued_heading_is_dropped() {
+        let mut snake = snake();
+
+        snake.request_direction(Direction::Left);
+        assert_eq!(snake.pending_turns(), 0);
+
+        snake.request_direction(Direction::Up);
+        snake.request_direction(Direction::Down);
+        snake.request_direction(Direction::Up);
+        assert_eq!(snake.pending_turns(), 1);
+
+        // Left would turn back from Right, after the queued Up it is a plain turn
+        snake.request_direction(Direction::Left);
+        assert_eq!(snake.pending_turns(), 2);
     }
 
-    fn at(&self, pos: u64) -> f64 {
-        let f_pos = pos as f64 / self.steps as f64;
-        (1.0 - f_pos) * self.start + f_pos * self.end
+    #[test]
+    fn one_turn_is_taken_per_cell() {
+        let mut simulation = simulation();
+        let head = simulation.board.snakes[0].head();
+
+        simulation.board.snakes[0].request_direction(Direction::Up);
+        simulation.board.snakes[0].request_direction(Direction::Left);
+
+        next_cell(&mut simulation);
+        let snake = &simulation.board.snakes[0];
+        assert!(snake.direction() == Direction::Up);
+        assert_eq!(snake.head(), Point { x: head.x, y: head.y - 1 });
+        assert_eq!(snake.pending_turns(), 1);
+
+        next_cell(&mut simulation);
+        let snake = &simulation.board.snakes[0];
+        assert!(snake.direction() == Direction::Left);
+        assert_eq!(snake.head(), Point { x: head.x - 1, y: head.y - 1 });
+        assert_eq!(snake.pending_turns(), 0);
     }
-}
 
-impl Iterator for FloatIterator {
-    type Item = f64;
+    #[test]
+    fn rotating_gate_clears_the_turns() {
+        let mut snake = snake();
+        let exit = Point { x: 2, y: 2 };
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.current >= self.current_back {
-            return None;
-        }
-        let result = self.at(self.current);
-        self.current += 1;
-        Some(result)
+        snake.request_direction(Direction::Up);
+        snake.request_direction(Direction::Left);
+        snake.teleport(exit, 0, false);
+        assert_eq!(snake.pending_turns(), 2);
+
+        snake.teleport(exit, 0, true);
+        assert!(snake.direction() == Direction::Right);
+        assert_eq!(snake.pending_turns(), 0);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,112 @@
+use crate::enemy::EnemyKind;
+use crate::food::FoodKind;
+use crate::power_up::PowerUpKind;
+use crate::simulation::Simulation;
+use crate::snake::Direction;
+
+use serde::{Deserialize, Serialize};
+
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BoardSnapshot {
+    pub size: u32,
+    pub snakes: Vec<SnakeSnapshot>,
+    pub food: (i32, i32),
+    pub food_kind: FoodKind,
+    pub power_up: Option<(i32, i32, PowerUpKind)>,
+    pub enemies: Vec<(i32, i32, EnemyKind)>,
+    // By pairs, gates[2n] and gates[2n + 1] lead to each other
+    pub gates: Vec<(i32, i32)>,
+    pub walls: Vec<(i32, i32)>,
+    pub steps: u64,
+    pub cleared: bool,
+    pub level: u32,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct SnakeSnapshot {
+    pub body: Vec<(i32, i32)>,
+    pub progress: f64,
+    pub trail: Option<(i32, i32)>,
+    pub direction: Direction,
+    pub score: u32,
+    pub effects: Vec<(PowerUpKind, f64)>,
+}
+
+impl BoardSnapshot {
+    pub fn new(simulation: &Simulation) -> BoardSnapshot {
+        let board = &simulation.board;
+
+        // The level keeps its walls in a set, a snapshot lists them in a fixed order
+        let mut walls: Vec<(i32, i32)> = board.config.computed_config.level.walls.iter().map(|wall| (wall.x, wall.y)).collect();
+        walls.sort_unstable();
+
+        BoardSnapshot {
+            size: board.config.config.board.board_block_length,
+            snakes: board.snakes
+                .iter()
+                .zip(simulation.scores.iter())
+                .map(|(snake, score)| SnakeSnapshot {
+                    body: snake.body.iter().map(|point| (point.x, point.y)).collect(),
+                    progress: snake.progress,
+                    trail: snake.trail.map(|point| (point.x, point.y)),
+                    direction: snake.direction(),
+                    score: score.scores["current"].count,
+                    effects: snake.effects.active.iter().map(|effect| (effect.kind, effect.remaining)).collect(),
+                })
+                .collect(),
+            food: (board.food.cell.x, board.food.cell.y),
+            food_kind: board.food.kind,
+            power_up: board.power_up.map(|power_up| (power_up.cell.x, power_up.cell.y, power_up.kind)),
+            enemies: board.enemies.iter().map(|enemy| (enemy.cell.x, enemy.cell.y, enemy.kind)).collect(),
+            gates: match &board.portal {
+                Some(portal) => portal.gates
+                    .iter()
+                    .map(|gate| (gate.cell.x, gate.cell.y))
+                    .collect(),
+                None => Vec::new(),
+            },
+            walls,
+            steps: simulation.steps,
+            cleared: simulation.cleared,
+            level: simulation.level,
+        }
+    }
+}
+
+impl BoardSnapshot {
+    // Slides the snakes on by a share of the next step, a head stops on its cell until the step moves it further
+    pub fn interpolate(&mut self, progress: f64) {
+        for snake in self.snakes.iter_mut() {
+            snake.progress = (snake.progress + progress).min(1.0);
+        }
+    }
+}
+
+impl SnakeSnapshot {
+    // Where each block is drawn: the head slides into its cell and the tail out of the trail cell.
+    // Across a wrap or a gate there is nothing in between, the block stays on its cell
+    pub fn blocks(&self) -> Vec<(f64, f64)> {
+        let slide = |(from_x, from_y): (i32, i32), (to_x, to_y): (i32, i32)| {
+            match (to_x - from_x).abs() + (to_y - from_y).abs() {
+                1 => (
+                    from_x as f64 + (to_x - from_x) as f64 * self.progress,
+                    from_y as f64 + (to_y - from_y) as f64 * self.progress,
+                ),
+                _ => (to_x as f64, to_y as f64),
+            }
+        };
+
+        let mut blocks: Vec<(f64, f64)> = self.body.iter().map(|(x, y)| (*x as f64, *y as f64)).collect();
+
+        if let (Some(head), Some(neck)) = (self.body.first(), self.body.get(1)) {
+            blocks[0] = slide(*neck, *head);
+        }
+
+        if let (Some(trail), Some(tail)) = (self.trail, self.body.last()) {
+            blocks.push(slide(trail, *tail));
+        }
+
+        blocks
+    }
+}
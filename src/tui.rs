@@ -0,0 +1,353 @@
+use crate::board_controller::{BoardController, GameState};
+use snake_rust::enemy::EnemyKind;
+use snake_rust::score::Score;
+use snake_rust::snapshot::BoardSnapshot;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, DisableFocusChange, EnableFocusChange, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use piston_window::{Button, ButtonArgs, ButtonState, Event, Input, Key, Loop, UpdateArgs};
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+
+const SNAKE_COLORS: [Color; 4] = [
+    Color::Rgb { r: 46, g: 204, b: 112 },
+    Color::Rgb { r: 230, g: 125, b: 33 },
+    Color::Rgb { r: 155, g: 89, b: 181 },
+    Color::Rgb { r: 242, g: 196, b: 15 },
+];
+const BOARD_COLOR: Color = Color::Rgb { r: 52, g: 73, b: 94 };
+const FOOD_COLORS: [Color; 4] = [
+    Color::Rgb { r: 255, g: 0, b: 0 },
+    Color::Rgb { r: 255, g: 214, b: 0 },
+    Color::Rgb { r: 232, g: 31, b: 140 },
+    Color::Rgb { r: 102, g: 153, b: 255 },
+];
+const WALL_COLOR: Color = Color::Rgb { r: 148, g: 166, b: 166 };
+const PATH_COLOR: Color = Color::Rgb { r: 93, g: 110, b: 126 };
+const POWER_UP_COLORS: [Color; 5] = [
+    Color::Rgb { r: 217, g: 217, b: 242 },
+    Color::Rgb { r: 51, g: 153, b: 219 },
+    Color::Rgb { r: 191, g: 56, b: 43 },
+    Color::Rgb { r: 26, g: 189, b: 156 },
+    Color::Rgb { r: 242, g: 156, b: 18 },
+];
+const ENEMY_COLORS: [Color; 3] = [
+    Color::Rgb { r: 140, g: 92, b: 245 },
+    Color::Rgb { r: 250, g: 140, b: 61 },
+    Color::Rgb { r: 153, g: 0, b: 0 },
+];
+const GATE_COLORS: [[Color; 2]; 4] = [
+    [Color::Rgb { r: 11, g: 252, b: 247 }, Color::Rgb { r: 252, g: 250, b: 140 }],
+    [Color::Rgb { r: 232, g: 77, b: 230 }, Color::Rgb { r: 250, g: 184, b: 247 }],
+    [Color::Rgb { r: 242, g: 115, b: 26 }, Color::Rgb { r: 255, g: 194, b: 140 }],
+    [Color::Rgb { r: 51, g: 217, b: 77 }, Color::Rgb { r: 178, g: 255, b: 184 }],
+];
+const TEXT_COLOR: Color = Color::White;
+const BANNER_COLOR: Color = Color::Red;
+const PANEL_COLOR: Color = Color::Black;
+
+// Every board cell is two terminal columns wide so it looks square
+const CELL_WIDTH: usize = 2;
+const COLUMN_WIDTH: usize = 16;
+
+#[derive(Copy, Clone, PartialEq)]
+struct Cell {
+    symbol: char,
+    foreground: Color,
+    background: Color,
+}
+
+struct Canvas {
+    cells: Vec<Vec<Cell>>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Canvas {
+        let blank = Cell {
+            symbol: ' ',
+            foreground: TEXT_COLOR,
+            background: PANEL_COLOR,
+        };
+
+        Canvas {
+            cells: vec![vec![blank; width]; height],
+        }
+    }
+
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, background: Color) {
+        for row in self.cells.iter_mut().skip(y).take(height) {
+            for cell in row.iter_mut().skip(x).take(width) {
+                *cell = Cell {
+                    symbol: ' ',
+                    foreground: TEXT_COLOR,
+                    background,
+                };
+            }
+        }
+    }
+
+    fn block(&mut self, x: usize, y: usize, symbols: &str, foreground: Color, background: Color) {
+        if let Some(row) = self.cells.get_mut(y) {
+            for (cell, symbol) in row.iter_mut().skip(x * CELL_WIDTH).zip(symbols.chars()) {
+                *cell = Cell {
+                    symbol,
+                    foreground,
+                    background,
+                };
+            }
+        }
+    }
+
+    // Text keeps the background already painted under it
+    fn text(&mut self, x: usize, y: usize, text: &str, foreground: Color) {
+        if let Some(row) = self.cells.get_mut(y) {
+            for (cell, symbol) in row.iter_mut().skip(x).zip(text.chars()) {
+                cell.symbol = symbol;
+                cell.foreground = foreground;
+            }
+        }
+    }
+
+    fn flush(&self, stdout: &mut Stdout) -> io::Result<()> {
+        for (y, row) in self.cells.iter().enumerate() {
+            queue!(stdout, MoveTo(0, y as u16))?;
+
+            let mut current: Option<(Color, Color)> = None;
+            for cell in row {
+                if current != Some((cell.foreground, cell.background)) {
+                    queue!(stdout, SetForegroundColor(cell.foreground), SetBackgroundColor(cell.background))?;
+                    current = Some((cell.foreground, cell.background));
+                }
+
+                queue!(stdout, Print(cell.symbol))?;
+            }
+
+            queue!(stdout, ResetColor, Clear(ClearType::UntilNewLine))?;
+        }
+
+        queue!(stdout, Clear(ClearType::FromCursorDown))?;
+
+        stdout.flush()
+    }
+}
+
+// Leaves the terminal usable again, even when the game panics
+struct Terminal {
+    stdout: Stdout,
+}
+
+impl Terminal {
+    // Built once raw mode is on, so dropping it turns raw mode off even when the screen fails to set up
+    fn enter() -> io::Result<Terminal> {
+        terminal::enable_raw_mode()?;
+
+        let mut terminal = Terminal { stdout: io::stdout() };
+        execute!(terminal.stdout, EnterAlternateScreen, EnableFocusChange, Hide, Clear(ClearType::All))?;
+
+        Ok(terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        execute!(self.stdout, Show, DisableFocusChange, LeaveAlternateScreen).ok();
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+// Terminal events are turned into piston events, the board controller runs unchanged
+pub fn run(controller: &mut BoardController) -> io::Result<()> {
+    let mut terminal = Terminal::enter()?;
+
+    let frame = Duration::from_secs_f64(1.0 / controller.simulation.board.config.computed_config.fps);
+    let mut last_update = Instant::now();
+
+    loop {
+        let deadline = last_update + frame;
+
+        while event::poll(deadline.saturating_duration_since(Instant::now()))? {
+            let events = match event::read()? {
+                event::Event::Key(key) if key.kind != KeyEventKind::Release => {
+                    // Raw mode swallows Ctrl+C, it quits like Esc
+                    if key.code == KeyCode::Esc || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)) {
+                        return Ok(());
+                    }
+
+                    key_events(key.code)
+                }
+                event::Event::FocusGained => vec![Event::Input(Input::Focus(true), None)],
+                event::Event::FocusLost => vec![Event::Input(Input::Focus(false), None)],
+                _ => Vec::new(),
+            };
+
+            for event in events.iter() {
+                controller.event(event);
+            }
+        }
+
+        let now = Instant::now();
+        let dt = (now - last_update).as_secs_f64();
+        last_update = now;
+
+        controller.event(&Event::Loop(Loop::Update(UpdateArgs { dt })));
+
+        draw(controller).flush(&mut terminal.stdout)?;
+    }
+}
+
+fn key_events(code: KeyCode) -> Vec<Event> {
+    let key = match code {
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::Enter => Key::Return,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Tab => Key::Tab,
+        KeyCode::Insert => Key::Insert,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::Char(' ') => Key::Space,
+        // Piston key codes follow the ASCII codes and F1 to F12 in a row
+        KeyCode::Char(c) if c.is_ascii_graphic() => Key::from(c.to_ascii_lowercase() as u32),
+        KeyCode::F(n) if (1..=12).contains(&n) => Key::from(Key::F1 as u32 + n as u32 - 1),
+        _ => Key::Unknown,
+    };
+
+    let mut events = vec![Event::Input(
+        Input::Button(ButtonArgs {
+            state: ButtonState::Press,
+            button: Button::Keyboard(key),
+            scancode: None,
+        }),
+        None,
+    )];
+
+    // Typed characters also go to the name entry, like the window text events
+    if let KeyCode::Char(c) = code {
+        events.push(Event::Input(Input::Text(c.to_string()), None));
+    }
+
+    events
+}
+
+fn draw(controller: &BoardController) -> Canvas {
+    let snapshot = BoardSnapshot::new(&controller.simulation);
+    let scores = &controller.simulation.scores;
+    let overlay = controller.overlay();
+
+    let size = snapshot.size as usize;
+    let board_width = size * CELL_WIDTH;
+    let overlay_width = overlay
+        .as_ref()
+        .map(|(title, lines)| lines.iter().chain(Some(title)).map(|line| line.chars().count() + 4).max().unwrap_or(0))
+        .unwrap_or(0);
+
+    let overlay_height = overlay.as_ref().map(|(_, lines)| lines.len() + 3).unwrap_or(0);
+    let height = size.max(7).max(overlay_height);
+
+    let mut canvas = Canvas::new((board_width + 1 + scores.len() * COLUMN_WIDTH).max(overlay_width), height);
+
+    canvas.fill(0, 0, board_width, size, BOARD_COLOR);
+
+    let cell = |(x, y): (i32, i32)| {
+        if x < 0 || y < 0 || x >= size as i32 || y >= size as i32 {
+            return None;
+        }
+
+        Some((x as usize, y as usize))
+    };
+
+    if let (GameState::Playing, Some(autopilot)) = (&controller.state, &controller.autopilot) {
+        for (x, y) in autopilot.path.iter().filter_map(|position| cell(*position)) {
+            canvas.block(x, y, "  ", TEXT_COLOR, PATH_COLOR);
+        }
+    }
+
+    for (x, y) in snapshot.walls.iter().filter_map(|position| cell(*position)) {
+        canvas.block(x, y, "  ", TEXT_COLOR, WALL_COLOR);
+    }
+
+    for (player, snake) in snapshot.snakes.iter().enumerate() {
+        for (x, y) in snake.body.iter().filter_map(|position| cell(*position)) {
+            canvas.block(x, y, "  ", TEXT_COLOR, SNAKE_COLORS[player]);
+        }
+    }
+
+    if let (false, Some((x, y))) = (snapshot.cleared, cell(snapshot.food)) {
+        canvas.block(x, y, "  ", TEXT_COLOR, FOOD_COLORS[snapshot.food_kind as usize]);
+    }
+
+    if let Some((x, y, kind)) = snapshot.power_up.and_then(|(x, y, kind)| cell((x, y)).map(|(x, y)| (x, y, kind))) {
+        canvas.block(x, y, "<>", POWER_UP_COLORS[kind as usize], BOARD_COLOR);
+    }
+
+    for (gate, (x, y)) in snapshot.gates.iter().enumerate().filter_map(|(gate, position)| cell(*position).map(|cell| (gate, cell))) {
+        canvas.block(x, y, "()", GATE_COLORS[gate / 2][gate % 2], BOARD_COLOR);
+    }
+
+    for (x, y, kind) in snapshot.enemies.iter().filter_map(|(x, y, kind)| cell((*x, *y)).map(|(x, y)| (x, y, *kind))) {
+        let symbols = match kind {
+            EnemyKind::Patrol => "[]",
+            EnemyKind::Ball => "()",
+            EnemyKind::Chaser => "><",
+        };
+
+        canvas.block(x, y, symbols, TEXT_COLOR, ENEMY_COLORS[kind as usize]);
+    }
+
+    draw_scores(&mut canvas, scores, &snapshot, board_width + 1);
+
+    if let Some(banner) = controller.banner() {
+        canvas.text(board_width + 1, height - 1, banner, BANNER_COLOR);
+    }
+
+    if let Some((title, lines)) = overlay {
+        canvas.fill(0, 0, overlay_width.max(board_width), overlay_height, PANEL_COLOR);
+        canvas.text(2, 1, &title, TEXT_COLOR);
+
+        for (i, line) in lines.iter().enumerate() {
+            canvas.text(2, 3 + i, line, TEXT_COLOR);
+        }
+    }
+
+    canvas
+}
+
+fn draw_scores(canvas: &mut Canvas, scores: &[Score], snapshot: &BoardSnapshot, x: usize) {
+    canvas.text(x, 1, &format!("Level : {}", snapshot.level), TEXT_COLOR);
+
+    for (column, score) in scores.iter().enumerate() {
+        let column_x = x + column * COLUMN_WIDTH;
+
+        canvas.text(column_x, 0, &score.title, TEXT_COLOR);
+
+        for (i, key) in ["current", "best", "death"].iter().enumerate() {
+            let element = &score.scores[*key];
+            canvas.text(column_x, 2 + i, &format!("{} : {}", ascii_uc_first(&element.title), element.count), TEXT_COLOR);
+        }
+
+        let effects = snapshot.snakes.get(column).map(|snake| &snake.effects[..]).unwrap_or(&[]);
+
+        for (i, (kind, remaining)) in effects.iter().enumerate() {
+            let line = format!("{} {:.1}s", ascii_uc_first(&kind.to_string()), remaining);
+            canvas.text(column_x, 6 + i, &line, POWER_UP_COLORS[*kind as usize]);
+        }
+    }
+}
+
+fn ascii_uc_first(text: &str) -> String {
+    let mut chars = text.chars();
+
+    match chars.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}